@@ -1,15 +1,153 @@
-use std::collections::HashMap;
+use std::{collections::BTreeMap, convert::TryFrom};
 
-use crate::blocks::Rule;
+use crate::{
+    blocks::Rule,
+    graph::{DependencyGraph, Edge, GraphError, Node, NodeIndex},
+};
 
+/// Tree of builds, each node owning its dependencies. Convenient to write by hand, and lowered
+/// into a [`DependencyGraph`] for everything else.
 #[derive(Clone, Debug)]
+#[allow(clippy::large_enum_variant)]
 pub enum NinjaAst {
     Source(String),
     Generated {
         name: String,
         rule: Rule,
         deps: Vec<NinjaAst>,
-        vars: HashMap<String, String>,
+        vars: BTreeMap<String, String>,
+        /// Outputs also produced by the rule, not listed in `$out`
+        implicit_outputs: Vec<String>,
+        /// Dependencies not listed in `$in`, such as headers
+        implicit_deps: Vec<NinjaAst>,
+        /// Dependencies that only need to exist before building, such as generated headers
+        order_only_deps: Vec<NinjaAst>,
+        /// Targets built whenever this one is, without being depended on
+        validations: Vec<NinjaAst>,
     },
     Default(Box<Self>),
 }
+
+impl NinjaAst {
+    /// Path of the file this node provides
+    pub fn target(&self) -> &str {
+        match self {
+            Self::Source(name) | Self::Generated { name, .. } => name,
+            Self::Default(inner) => inner.target(),
+        }
+    }
+
+    /// Adds the tree to the graph, returning the node providing the target. Targets already in
+    /// the graph, such as a library reached from several dependents, are reused.
+    pub fn add_to_graph(self, graph: &mut DependencyGraph) -> Result<NodeIndex, GraphError> {
+        if let Some(ix) = graph.node_index(self.target()) {
+            if let Self::Default(_) = self {
+                graph.add_default(ix);
+            }
+            return Ok(ix);
+        }
+        match self {
+            Self::Source(path) => Ok(graph.add_source(path)),
+            Self::Generated {
+                name,
+                rule,
+                deps,
+                vars,
+                implicit_outputs,
+                implicit_deps,
+                order_only_deps,
+                validations,
+            } => {
+                let ix = graph.add_node(Node::Generated {
+                    rule,
+                    outputs: vec![name],
+                    implicit_outputs,
+                    vars,
+                    validations: validations.iter().map(|v| v.target().to_owned()).collect(),
+                });
+                for (kind, asts) in [
+                    (Edge::Explicit, deps),
+                    (Edge::Implicit, implicit_deps),
+                    (Edge::OrderOnly, order_only_deps),
+                ] {
+                    for ast in asts {
+                        let dep = ast.add_to_graph(graph)?;
+                        graph.add_dependency_kind(ix, dep, kind)?;
+                    }
+                }
+                // Validations usually depend on this node, so they are added afterwards
+                for validation in validations {
+                    validation.add_to_graph(graph)?;
+                }
+                Ok(ix)
+            }
+            Self::Default(inner) => {
+                let ix = inner.add_to_graph(graph)?;
+                graph.add_default(ix);
+                Ok(ix)
+            }
+        }
+    }
+}
+
+impl TryFrom<NinjaAst> for DependencyGraph {
+    type Error = GraphError;
+
+    fn try_from(ast: NinjaAst) -> Result<Self, Self::Error> {
+        let mut graph = Self::default();
+        ast.add_to_graph(&mut graph)?;
+        Ok(graph)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, convert::TryFrom};
+
+    use crate::{DependencyGraph, Edge, NinjaAst::*, Rule};
+
+    #[test]
+    fn ast_lowers_into_graph() {
+        let ccrule = Rule::new("cc".to_owned(), "gcc -c $in -o $out".to_owned());
+        let ldrule = Rule::new("ld".to_owned(), "gcc $in -o $out".to_owned());
+        let object = |name: &str, source: &str| Generated {
+            name: name.to_owned(),
+            rule: ccrule.clone(),
+            deps: vec![Source(source.to_owned())],
+            vars: BTreeMap::new(),
+            implicit_outputs: vec![],
+            implicit_deps: vec![Source("config.h".to_owned())],
+            order_only_deps: vec![],
+            validations: vec![],
+        };
+        let ast = Default(Box::new(Generated {
+            name: "app".to_owned(),
+            rule: ldrule,
+            deps: vec![object("main.o", "main.c"), object("util.o", "util.c")],
+            vars: BTreeMap::new(),
+            implicit_outputs: vec![],
+            implicit_deps: vec![],
+            order_only_deps: vec![],
+            validations: vec![],
+        }));
+
+        let graph = DependencyGraph::try_from(ast).unwrap();
+        // The shared header is a single node
+        assert_eq!(6, graph.node_count());
+        let app = graph.node_index("app").unwrap();
+        let objects = vec![
+            (Edge::Explicit, graph.node_index("main.o").unwrap()),
+            (Edge::Explicit, graph.node_index("util.o").unwrap()),
+        ];
+        assert_eq!(objects, graph.dependencies(app));
+        let header = graph.node_index("config.h").unwrap();
+        assert_eq!(
+            vec![
+                (Edge::Explicit, graph.node_index("util.c").unwrap()),
+                (Edge::Implicit, header),
+            ],
+            graph.dependencies(objects[1].1)
+        );
+        assert_eq!(vec![&graph[app]], graph.defaults().collect::<Vec<_>>());
+    }
+}
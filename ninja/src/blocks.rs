@@ -1,6 +1,6 @@
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 
-use crate::pretty::variable;
+use crate::pretty::{escape_path, escape_value, variable};
 
 use super::pretty::{
     Annotation::{self, *},
@@ -15,6 +15,33 @@ pub struct Rule {
     pub(crate) command: String,
     pub(crate) description: Option<String>,
     pub(crate) depfile: Option<String>,
+    pub(crate) deps: Option<Deps>,
+    pub(crate) msvc_deps_prefix: Option<String>,
+    pub(crate) pool: Option<String>,
+    pub(crate) restat: bool,
+    pub(crate) generator: bool,
+    pub(crate) rspfile: Option<String>,
+    pub(crate) rspfile_content: Option<String>,
+    pub(crate) dyndep: Option<String>,
+}
+
+/// Format of the dependency information produced by a rule, which ninja folds into its own
+/// `.ninja_deps` database
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum Deps {
+    /// Makefile-style depfile, as written by `gcc -MD` or `clang -MD`
+    Gcc,
+    /// `/showIncludes` output of the MSVC compiler
+    Msvc,
+}
+
+impl Deps {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Gcc => "gcc",
+            Self::Msvc => "msvc",
+        }
+    }
 }
 
 impl PartialEq<Rule> for Rule {
@@ -43,6 +70,19 @@ impl Pretty<Annotation> for Rule {
         D: DocAllocator<'a, Annotation>,
         D::Doc: Clone,
     {
+        let flag = |b: bool| if b { Some("1".to_owned()) } else { None };
+        let vars = vec![
+            ("description", self.description.clone()),
+            ("depfile", self.depfile.clone()),
+            ("deps", self.deps.map(|d| d.as_str().to_owned())),
+            ("msvc_deps_prefix", self.msvc_deps_prefix.clone()),
+            ("pool", self.pool.clone()),
+            ("restat", flag(self.restat)),
+            ("generator", flag(self.generator)),
+            ("rspfile", self.rspfile.clone()),
+            ("rspfile_content", self.rspfile_content.clone()),
+            ("dyndep", self.dyndep.clone()),
+        ];
         let doc = allocator
             .text("rule")
             .annotate(Keyword)
@@ -51,21 +91,12 @@ impl Pretty<Annotation> for Rule {
             .append(allocator.hardline())
             .append(variable("command", allocator.text(self.command.clone()), allocator).indent(2))
             .append(allocator.hardline());
-        let doc = if let Some(d) = &self.description {
-            doc.append(variable("description", allocator.text(d.clone()), allocator).indent(2))
-                .append(allocator.hardline())
-        } else {
-            doc
-        };
-        if let Some(d) = &self.depfile {
-            doc.append(
-                variable("depfile", allocator.text(d.clone()), allocator)
-                    .indent(2)
-                    .append(allocator.hardline()),
-            )
-        } else {
-            doc
-        }
+        vars.into_iter()
+            .filter_map(|(name, value)| value.map(|v| (name, v)))
+            .fold(doc, |doc, (name, value)| {
+                doc.append(variable(name, allocator.text(value), allocator).indent(2))
+                    .append(allocator.hardline())
+            })
     }
 }
 
@@ -76,9 +107,52 @@ impl Rule {
             command,
             description: None,
             depfile: None,
+            deps: None,
+            msvc_deps_prefix: None,
+            pool: None,
+            restat: false,
+            generator: false,
+            rspfile: None,
+            rspfile_content: None,
+            dyndep: None,
+        }
+    }
+
+    /// Ninja's built-in rule, for aliases and targets without a command
+    pub fn phony() -> Self {
+        Self::new("phony".to_owned(), String::new())
+    }
+
+    pub fn is_phony(&self) -> bool {
+        self.name == "phony"
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Unevaluated value of a rule variable
+    pub fn variable(&self, name: &str) -> Option<&str> {
+        match name {
+            "command" => Some(&self.command),
+            "description" => self.description.as_deref(),
+            "depfile" => self.depfile.as_deref(),
+            "deps" => self.deps.map(|d| d.as_str()),
+            "msvc_deps_prefix" => self.msvc_deps_prefix.as_deref(),
+            "pool" => self.pool.as_deref(),
+            "restat" => self.restat.then_some("1"),
+            "generator" => self.generator.then_some("1"),
+            "rspfile" => self.rspfile.as_deref(),
+            "rspfile_content" => self.rspfile_content.as_deref(),
+            "dyndep" => self.dyndep.as_deref(),
+            _ => None,
         }
     }
 
+    pub fn command(&self) -> &str {
+        &self.command
+    }
+
     pub fn set_description<S: Into<Option<String>>>(mut self, description: S) -> Self {
         self.description = description.into();
         self
@@ -89,6 +163,46 @@ impl Rule {
         self
     }
 
+    pub fn set_deps<D: Into<Option<Deps>>>(mut self, deps: D) -> Self {
+        self.deps = deps.into();
+        self
+    }
+
+    pub fn set_msvc_deps_prefix<S: Into<Option<String>>>(mut self, prefix: S) -> Self {
+        self.msvc_deps_prefix = prefix.into();
+        self
+    }
+
+    pub fn set_pool<S: Into<Option<String>>>(mut self, pool: S) -> Self {
+        self.pool = pool.into();
+        self
+    }
+
+    pub fn set_restat(mut self, restat: bool) -> Self {
+        self.restat = restat;
+        self
+    }
+
+    pub fn set_generator(mut self, generator: bool) -> Self {
+        self.generator = generator;
+        self
+    }
+
+    pub fn set_rspfile<S: Into<Option<String>>>(mut self, rspfile: S) -> Self {
+        self.rspfile = rspfile.into();
+        self
+    }
+
+    pub fn set_rspfile_content<S: Into<Option<String>>>(mut self, content: S) -> Self {
+        self.rspfile_content = content.into();
+        self
+    }
+
+    pub fn set_dyndep<S: Into<Option<String>>>(mut self, dyndep: S) -> Self {
+        self.dyndep = dyndep.into();
+        self
+    }
+
     pub fn get_ref(&self) -> RuleRef {
         RuleRef(self.name.clone())
     }
@@ -109,9 +223,9 @@ impl From<Rule> for RuleRef {
     }
 }
 
-impl Into<String> for RuleRef {
-    fn into(self) -> String {
-        self.0
+impl From<RuleRef> for String {
+    fn from(rule: RuleRef) -> Self {
+        rule.0
     }
 }
 
@@ -119,8 +233,16 @@ impl Into<String> for RuleRef {
 pub struct Build {
     pub rule: String,
     pub outputs: Vec<String>,
+    /// Outputs not listed in `$out`, written after `|`
+    pub implicit_outputs: Vec<String>,
     pub inputs: Vec<String>,
-    pub vars: HashMap<String, String>,
+    /// Dependencies not listed in `$in`, written after `|`
+    pub implicit_inputs: Vec<String>,
+    /// Dependencies that only need to be built beforehand, written after `||`
+    pub order_only_inputs: Vec<String>,
+    /// Targets built along with this one without being depended on, written after `|@`
+    pub validations: Vec<String>,
+    pub vars: BTreeMap<String, String>,
 }
 
 impl PartialEq<Self> for Build {
@@ -136,30 +258,35 @@ impl Pretty<Annotation> for Build {
         D: DocAllocator<'a, Annotation>,
         D::Doc: Clone,
     {
-        let inputs = self
-            .inputs
-            .iter()
-            .map(|i| allocator.text(i.clone()) /* .double_quotes() */);
-        let outputs = self
-            .outputs
-            .iter()
-            .map(|o| allocator.text(o.clone()) /* .double_quotes() */);
+        let paths = |sep: Option<&'static str>, paths: &[String]| {
+            if paths.is_empty() {
+                return allocator.nil();
+            }
+            let paths = paths
+                .iter()
+                .map(|p| allocator.space().append(allocator.text(escape_path(p).into_owned())));
+            match sep {
+                Some(sep) => allocator.space().append(sep).append(allocator.concat(paths)),
+                None => allocator.concat(paths),
+            }
+        };
         let vars = self
             .vars
             .iter()
-            .map(|(k, v)| variable(k.clone(), allocator.text(v.clone()), allocator));
+            .map(|(k, v)| variable(k.clone(), allocator.text(escape_value(v).into_owned()), allocator));
         allocator
             .text("build")
             .annotate(Annotation::Keyword)
-            .append(allocator.space())
             .append({
-                let base = allocator
-                    .intersperse(outputs, allocator.space())
+                let base = paths(None, &self.outputs)
+                    .append(paths(Some("|"), &self.implicit_outputs))
                     .append(":")
                     .append(allocator.space())
                     .append(self.rule.clone())
-                    .append(allocator.space())
-                    .append(allocator.intersperse(inputs, allocator.space()))
+                    .append(paths(None, &self.inputs))
+                    .append(paths(Some("|"), &self.implicit_inputs))
+                    .append(paths(Some("||"), &self.order_only_inputs))
+                    .append(paths(Some("|@"), &self.validations))
                     .append(allocator.hardline());
                 if vars.len() == 0 {
                     base
@@ -176,8 +303,12 @@ impl Build {
         Self {
             rule,
             outputs,
+            implicit_outputs: vec![],
             inputs,
-            vars: HashMap::new(),
+            implicit_inputs: vec![],
+            order_only_inputs: vec![],
+            validations: vec![],
+            vars: BTreeMap::new(),
         }
     }
 
@@ -189,6 +320,47 @@ impl Build {
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct BuildRef(String);
 
+/// Pool limiting the number of concurrent jobs of the rules and builds assigned to it
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Pool {
+    pub(crate) name: String,
+    pub(crate) depth: usize,
+}
+
+impl Pretty<Annotation> for Pool {
+    fn pretty<'a, D>(&self, allocator: &'a D) -> DocBuilder<'a, D, Annotation>
+    where
+        D: DocAllocator<'a, Annotation>,
+        D::Doc: Clone,
+    {
+        allocator
+            .text("pool")
+            .annotate(Keyword)
+            .append(allocator.space())
+            .append(allocator.text(self.name.clone()))
+            .append(allocator.hardline())
+            .append(variable("depth", allocator.text(self.depth.to_string()), allocator).indent(2))
+            .append(allocator.hardline())
+    }
+}
+
+impl Pool {
+    pub fn new<S: Into<String>>(name: S, depth: usize) -> Self {
+        Self {
+            name: name.into(),
+            depth,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+}
+
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct DefaultBlock(pub BuildRef);
 
@@ -203,7 +375,7 @@ impl Pretty<Annotation> for DefaultBlock {
             .text("default")
             .annotate(Annotation::Keyword)
             .append(allocator.space())
-            .append(build.clone())
+            .append(escape_path(build).into_owned())
     }
 }
 
@@ -211,7 +383,7 @@ impl Pretty<Annotation> for DefaultBlock {
 mod tests {
     use pretty::RcAllocator;
 
-    use crate::{Build, Pretty, Rule};
+    use crate::{Build, Deps, Pool, Pretty, Rule};
 
     #[test]
     fn rule_eq() {
@@ -259,6 +431,43 @@ mod tests {
         );
     }
 
+    #[test]
+    fn pretty_rule_all_variables() {
+        let rule = Rule::new("cc".to_owned(), "cl /showIncludes $in".to_owned())
+            .set_deps(Deps::Msvc)
+            .set_msvc_deps_prefix("Note: including file:".to_owned())
+            .set_pool("console".to_owned())
+            .set_restat(true)
+            .set_generator(true)
+            .set_rspfile("$out.rsp".to_owned())
+            .set_rspfile_content("$in".to_owned())
+            .set_dyndep("$out.dd".to_owned());
+
+        let mut renderer = vec![];
+        rule.pretty(&RcAllocator)
+            .into_doc()
+            .render(80, &mut renderer)
+            .unwrap();
+        assert_eq!(
+            "rule cc\n  command = cl /showIncludes $in\n  deps = msvc\n  \
+             msvc_deps_prefix = Note: including file:\n  pool = console\n  restat = 1\n  \
+             generator = 1\n  rspfile = $out.rsp\n  rspfile_content = $in\n  dyndep = $out.dd\n",
+            String::from_utf8(renderer).unwrap()
+        );
+    }
+
+    #[test]
+    fn pretty_pool() {
+        let pool = Pool::new("link_pool", 4);
+
+        let mut renderer = vec![];
+        pool.pretty(&RcAllocator)
+            .into_doc()
+            .render(80, &mut renderer)
+            .unwrap();
+        assert_eq!(b"pool link_pool\n  depth = 4\n"[..], renderer[..]);
+    }
+
     #[test]
     fn build_pretty() {
         let rule = Rule::new("a".to_owned(), "cmd".to_owned());
@@ -285,4 +494,60 @@ mod tests {
             .unwrap();
         assert_eq!(b"build a.o: a a.c\n  flags = -lm\n"[..], renderer[..]);
     }
+
+    #[test]
+    fn build_pretty_escapes() {
+        let rule = Rule::new("cc".to_owned(), "gcc -c $in -o $out".to_owned());
+        let mut build = Build::new(
+            rule.get_ref(),
+            vec!["my project/a:b.o".to_owned()],
+            vec!["my project/a:b.c".to_owned(), "$weird.c".to_owned()],
+        );
+        build.vars.insert("flags".to_owned(), "-DPRICE=$5".to_owned());
+
+        let mut renderer = vec![];
+        build
+            .pretty(&RcAllocator)
+            .into_doc()
+            .render(80, &mut renderer)
+            .unwrap();
+        assert_eq!(
+            "build my$ project/a$:b.o: cc my$ project/a$:b.c $$weird.c\n  flags = -DPRICE=$$5\n",
+            String::from_utf8(renderer).unwrap()
+        );
+    }
+
+    #[test]
+    fn build_pretty_extra_dependencies() {
+        let rule = Rule::new("a".to_owned(), "cmd".to_owned());
+        let mut b1 = Build::new(rule.get_ref(), vec!["a.o".to_owned()], vec![]);
+        b1.order_only_inputs.push("gen.h".to_owned());
+        let mut b2 = Build::new(
+            rule.get_ref(),
+            vec!["a.o".to_owned()],
+            vec!["a.c".to_owned(), "b.c".to_owned()],
+        );
+        b2.implicit_outputs.push("a.stamp".to_owned());
+        b2.implicit_inputs.push("config.h".to_owned());
+        b2.order_only_inputs.push("gen.h".to_owned());
+        b2.validations.push("a.check".to_owned());
+
+        let mut renderer = vec![];
+        b1.pretty(&RcAllocator)
+            .into_doc()
+            .render(80, &mut renderer)
+            .unwrap();
+        assert_eq!(b"build a.o: a || gen.h\n"[..], renderer[..]);
+
+        renderer.clear();
+
+        b2.pretty(&RcAllocator)
+            .into_doc()
+            .render(80, &mut renderer)
+            .unwrap();
+        assert_eq!(
+            b"build a.o | a.stamp: a a.c b.c | config.h || gen.h |@ a.check\n"[..],
+            renderer[..]
+        );
+    }
 }
@@ -0,0 +1,83 @@
+use std::{fs::File, io::BufWriter, path::Path};
+
+use serde::Serialize;
+
+use crate::{
+    executor::Job,
+    graph::{DependencyGraph, Node},
+};
+
+/// Entry of a JSON compilation database, as read by clangd and other tooling
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct CompileCommand {
+    pub directory: String,
+    pub command: String,
+    pub file: String,
+    pub output: String,
+}
+
+/// Compilation database of the builds using one of the given rules, like `ninja -t compdb`.
+/// Commands run from `dir`, the directory of the build file, and are fully expanded.
+pub fn compile_commands<P: AsRef<Path>, S: AsRef<str>>(
+    graph: &DependencyGraph,
+    dir: P,
+    rules: &[S],
+) -> Vec<CompileCommand> {
+    let directory = dir.as_ref().display().to_string();
+    graph
+        .topological_order()
+        .into_iter()
+        .filter(|&n| match &graph[n] {
+            Node::Generated { rule, .. } => rules.iter().any(|r| r.as_ref() == rule.name),
+            Node::Source(_) => false,
+        })
+        .filter_map(|n| {
+            let job = Job::new(graph, n);
+            Some(CompileCommand {
+                directory: directory.clone(),
+                file: job.inputs.first()?.clone(),
+                output: job.outputs.first()?.clone(),
+                command: job.command,
+            })
+        })
+        .collect()
+}
+
+/// Writes the compilation database as `compile_commands.json` in `dir`
+pub fn write_compile_commands<P: AsRef<Path>>(
+    commands: &[CompileCommand],
+    dir: P,
+) -> std::io::Result<()> {
+    let file = BufWriter::new(File::create(dir.as_ref().join("compile_commands.json"))?);
+    serde_json::to_writer_pretty(file, commands)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compile_commands, CompileCommand};
+    use crate::NinjaFile;
+
+    #[test]
+    fn compile_commands_are_expanded() {
+        let graph = NinjaFile::parse(
+            "cflags = -O2\n\
+             rule cc\n  command = gcc $cflags $flags -c -o $out $in\n\
+             rule ld\n  command = gcc -o $out $in\n\
+             build a.o: cc ../src/a.c | a.h\n  flags = -Iinclude\n\
+             build my app: ld a.o\n",
+        )
+        .unwrap()
+        .to_graph()
+        .unwrap();
+        assert_eq!(
+            vec![CompileCommand {
+                directory: "/build".to_owned(),
+                command: "gcc -O2 -Iinclude -c -o a.o ../src/a.c".to_owned(),
+                file: "../src/a.c".to_owned(),
+                output: "a.o".to_owned(),
+            }],
+            compile_commands(&graph, "/build", &["cc"])
+        );
+    }
+}
@@ -0,0 +1,631 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    process::{Command, Output},
+    sync::mpsc,
+    time::SystemTime,
+};
+
+use thiserror::Error;
+
+use crate::{
+    blocks::{Pool, Rule},
+    graph::{DependencyGraph, Edge, Node, NodeIndex},
+    parser::{evaluate, ParseError},
+};
+
+/// Maximum nesting of rule variables referencing each other
+const MAX_VARIABLE_DEPTH: usize = 16;
+
+#[derive(Debug, Error)]
+pub enum ExecError {
+    #[error("'{0}', needed by '{1}', is missing and no known rule makes it")]
+    MissingInput(String, String),
+    #[error("Unknown target '{0}'")]
+    UnknownTarget(String),
+    #[error("{0}: {1}")]
+    Io(String, #[source] std::io::Error),
+    #[error("Build of '{0}' failed")]
+    Failed(String),
+    #[error(transparent)]
+    Parse(#[from] ParseError),
+}
+
+fn io_error<P: AsRef<Path>>(path: P) -> impl FnOnce(std::io::Error) -> ExecError {
+    move |err| ExecError::Io(path.as_ref().display().to_string(), err)
+}
+
+/// Builds a dependency graph without the `ninja` binary. Like ninja, nodes are rebuilt when an
+/// output is missing or older than one of its inputs, including the headers listed in its
+/// depfile. Commands are not recorded, so changing a command alone does not trigger a rebuild.
+#[derive(Clone, Debug)]
+pub struct Executor {
+    dir: PathBuf,
+    jobs: usize,
+    pools: HashMap<String, usize>,
+}
+
+impl Executor {
+    /// Creates an executor running commands from `dir`, the directory of the build file
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        let mut pools = HashMap::new();
+        pools.insert("console".to_owned(), 1);
+        Self {
+            dir: dir.into(),
+            jobs: std::thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(1),
+            pools,
+        }
+    }
+
+    /// Sets the maximum number of commands running at the same time
+    pub fn set_jobs(mut self, jobs: usize) -> Self {
+        self.jobs = jobs.max(1);
+        self
+    }
+
+    pub fn add_pool(mut self, pool: &Pool) -> Self {
+        self.pools.insert(pool.name.clone(), pool.depth);
+        self
+    }
+
+    /// Builds the default targets of the graph, or all of them when there are none. Returns the
+    /// number of commands that were run, zero when everything was up to date.
+    pub fn build(&self, graph: &DependencyGraph) -> Result<usize, ExecError> {
+        let mut targets = graph
+            .defaults()
+            .flat_map(|n| n.explicit_files())
+            .filter_map(|f| graph.node_index(f))
+            .collect::<Vec<_>>();
+        if targets.is_empty() {
+            targets = graph
+                .indices()
+                .filter(|&n| graph.dependents(n).is_empty())
+                .collect();
+        }
+        self.build_nodes(graph, targets)
+    }
+
+    /// Builds the given targets and their dependencies. Returns the number of commands that were
+    /// run, zero when everything was up to date.
+    pub fn build_targets<S: AsRef<str>>(
+        &self,
+        graph: &DependencyGraph,
+        targets: &[S],
+    ) -> Result<usize, ExecError> {
+        let targets = targets
+            .iter()
+            .map(|t| {
+                graph
+                    .node_index(t)
+                    .ok_or_else(|| ExecError::UnknownTarget(t.as_ref().to_owned()))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        self.build_nodes(graph, targets)
+    }
+
+    fn build_nodes(
+        &self,
+        graph: &DependencyGraph,
+        targets: Vec<NodeIndex>,
+    ) -> Result<usize, ExecError> {
+        let wanted = wanted_nodes(graph, targets);
+        let dirty = self.dirty_nodes(graph, &wanted)?;
+        let total = dirty.iter().filter(|&&n| !is_phony(&graph[n])).count();
+        if total == 0 {
+            return Ok(0);
+        }
+
+        let mut waiting = HashMap::new();
+        let mut ready = VecDeque::new();
+        for n in graph.topological_order() {
+            if !dirty.contains(&n) {
+                continue;
+            }
+            let count = graph
+                .dependencies(n)
+                .into_iter()
+                .filter(|(_, d)| dirty.contains(d))
+                .count();
+            if count == 0 {
+                ready.push_back(n);
+            } else {
+                waiting.insert(n, count);
+            }
+        }
+
+        let (tx, rx) = mpsc::channel();
+        let mut pool_use: HashMap<String, usize> = HashMap::new();
+        let mut running = 0;
+        let mut finished = 0;
+        let mut failure = None;
+        // Error preventing the build from going on, returned once the running jobs are done
+        let mut error = None;
+        let mut complete = |n: NodeIndex, ready: &mut VecDeque<NodeIndex>| {
+            for dependent in graph.dependents(n) {
+                if let Some(count) = waiting.get_mut(&dependent) {
+                    *count -= 1;
+                    if *count == 0 {
+                        waiting.remove(&dependent);
+                        ready.push_back(dependent);
+                    }
+                }
+            }
+        };
+        loop {
+            let mut i = 0;
+            while failure.is_none() && error.is_none() && running < self.jobs && i < ready.len() {
+                let n = ready[i];
+                let (rule, job) = match &graph[n] {
+                    Node::Generated { rule, .. } if rule.is_phony() => {
+                        ready.remove(i);
+                        complete(n, &mut ready);
+                        continue;
+                    }
+                    Node::Generated { rule, .. } => (rule, Job::new(graph, n)),
+                    Node::Source(_) => unreachable!("sources are never dirty"),
+                };
+                if let Some(pool) = &rule.pool {
+                    let used = pool_use.entry(pool.clone()).or_default();
+                    if *used >= self.pools.get(pool).copied().unwrap_or(usize::MAX) {
+                        i += 1;
+                        continue;
+                    }
+                    *used += 1;
+                }
+                ready.remove(i);
+                if let Err(err) = job.prepare(&self.dir) {
+                    if let Some(pool) = &rule.pool {
+                        *pool_use.get_mut(pool).unwrap() -= 1;
+                    }
+                    error = Some(err);
+                    break;
+                }
+                running += 1;
+                let tx = tx.clone();
+                let dir = self.dir.clone();
+                std::thread::spawn(move || {
+                    let output = shell(&job.command).current_dir(&dir).output();
+                    // The receiver outlives all jobs
+                    let _ = tx.send((n, job, output));
+                });
+            }
+            if running == 0 {
+                break;
+            }
+
+            let (n, job, output) = rx.recv().expect("job threads always send their result");
+            running -= 1;
+            if let Node::Generated {
+                rule: Rule {
+                    pool: Some(pool), ..
+                },
+                ..
+            } = &graph[n]
+            {
+                *pool_use.get_mut(pool).unwrap() -= 1;
+            }
+            finished += 1;
+            println!("[{}/{}] {}", finished, total, job.description);
+            let output = match output {
+                Ok(output) => output,
+                Err(err) => {
+                    error.get_or_insert(io_error(&job.command)(err));
+                    continue;
+                }
+            };
+            print_output(&output);
+            if output.status.success() {
+                match job.cleanup(&self.dir) {
+                    Ok(()) => complete(n, &mut ready),
+                    Err(err) => {
+                        error.get_or_insert(err);
+                    }
+                }
+            } else {
+                println!("FAILED: {}\n{}", job.outputs.join(" "), job.command);
+                failure.get_or_insert_with(|| job.outputs.join(" "));
+            }
+        }
+        if let Some(err) = error {
+            return Err(err);
+        }
+        match failure {
+            Some(outputs) => Err(ExecError::Failed(outputs)),
+            None => Ok(finished),
+        }
+    }
+
+    /// Nodes needing to be rebuilt, as they or one of their dependencies are out of date
+    fn dirty_nodes(
+        &self,
+        graph: &DependencyGraph,
+        wanted: &HashSet<NodeIndex>,
+    ) -> Result<HashSet<NodeIndex>, ExecError> {
+        let mut dirty = HashSet::new();
+        for n in graph.topological_order() {
+            if !wanted.contains(&n) {
+                continue;
+            }
+            match &graph[n] {
+                Node::Source(path) => {
+                    if self.mtime(path)?.is_none() {
+                        if let Some(dependent) = graph.dependents(n).first() {
+                            let dependent = graph[*dependent].files()[0].to_owned();
+                            return Err(ExecError::MissingInput(path.clone(), dependent));
+                        }
+                    }
+                }
+                Node::Generated { .. } => {
+                    if self.is_dirty(graph, n, &dirty)? {
+                        dirty.insert(n);
+                    }
+                }
+            }
+        }
+        Ok(dirty)
+    }
+
+    fn is_dirty(
+        &self,
+        graph: &DependencyGraph,
+        n: NodeIndex,
+        dirty: &HashSet<NodeIndex>,
+    ) -> Result<bool, ExecError> {
+        let deps = graph
+            .dependencies(n)
+            .into_iter()
+            .filter(|(kind, _)| *kind != Edge::OrderOnly)
+            .map(|(_, d)| d)
+            .collect::<Vec<_>>();
+        if deps.iter().any(|d| dirty.contains(d)) {
+            return Ok(true);
+        }
+        let mut oldest_output = None;
+        for output in graph[n].files() {
+            match self.mtime(output)? {
+                Some(mtime) => {
+                    oldest_output = Some(oldest_output.map_or(mtime, |o: SystemTime| o.min(mtime)))
+                }
+                None => return Ok(deps.is_empty() || !is_phony(&graph[n])),
+            }
+        }
+        let oldest_output = match oldest_output {
+            Some(mtime) if !is_phony(&graph[n]) => mtime,
+            _ => return Ok(false),
+        };
+        for dep in deps {
+            for file in graph[dep].explicit_files() {
+                if self.mtime(file)?.is_some_and(|t| t > oldest_output) {
+                    return Ok(true);
+                }
+            }
+        }
+        let job = Job::new(graph, n);
+        if let Some(depfile) = job.depfile {
+            let content = match fs::read_to_string(self.dir.join(&depfile)) {
+                Ok(content) => content,
+                Err(err) if err.kind() == ErrorKind::NotFound => return Ok(true),
+                Err(err) => return Err(io_error(depfile)(err)),
+            };
+            for file in parse_depfile(&content) {
+                match self.mtime(&file)? {
+                    Some(mtime) if mtime <= oldest_output => {}
+                    _ => return Ok(true),
+                }
+            }
+        }
+        Ok(false)
+    }
+
+    fn mtime(&self, path: &str) -> Result<Option<SystemTime>, ExecError> {
+        match fs::metadata(self.dir.join(path)) {
+            Ok(metadata) => Ok(Some(metadata.modified().map_err(io_error(path))?)),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(io_error(path)(err)),
+        }
+    }
+}
+
+/// Nodes needed to build the targets, including validations
+fn wanted_nodes(graph: &DependencyGraph, targets: Vec<NodeIndex>) -> HashSet<NodeIndex> {
+    let mut wanted = HashSet::new();
+    let mut stack = targets;
+    while let Some(n) = stack.pop() {
+        if !wanted.insert(n) {
+            continue;
+        }
+        stack.extend(graph.dependencies(n).into_iter().map(|(_, d)| d));
+        if let Node::Generated { validations, .. } = &graph[n] {
+            stack.extend(validations.iter().filter_map(|v| graph.node_index(v)));
+        }
+    }
+    wanted
+}
+
+fn is_phony(node: &Node) -> bool {
+    matches!(node, Node::Generated { rule, .. } if rule.is_phony())
+}
+
+/// Variables visible to the commands of a build
+struct Scope<'a> {
+    rule: &'a Rule,
+    vars: &'a BTreeMap<String, String>,
+    inputs: Vec<&'a str>,
+    outputs: Vec<&'a str>,
+}
+
+impl<'a> Scope<'a> {
+    fn get(&self, name: &str, depth: usize) -> Option<String> {
+        let escaped = |paths: &[&str]| {
+            paths
+                .iter()
+                .map(|p| shell_escape(p))
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        match name {
+            "in" => Some(escaped(&self.inputs)),
+            "in_newline" => Some(self.inputs.join("\n")),
+            "out" => Some(escaped(&self.outputs)),
+            _ => self.vars.get(name).cloned().or_else(|| {
+                let value = self.rule.variable(name)?;
+                if depth >= MAX_VARIABLE_DEPTH {
+                    return None;
+                }
+                evaluate(value, |name| self.get(name, depth + 1)).ok()
+            }),
+        }
+    }
+}
+
+/// Command of a build, with its variables expanded
+#[derive(Clone, Debug)]
+pub(crate) struct Job {
+    pub(crate) command: String,
+    pub(crate) description: String,
+    /// Files listed in `$in`
+    pub(crate) inputs: Vec<String>,
+    pub(crate) outputs: Vec<String>,
+    pub(crate) depfile: Option<String>,
+    /// Path and content of the response file
+    pub(crate) rspfile: Option<(String, String)>,
+}
+
+impl Job {
+    /// Expands the rule variables of the node
+    pub(crate) fn new(graph: &DependencyGraph, n: NodeIndex) -> Self {
+        let (rule, outputs, vars) = match &graph[n] {
+            Node::Generated {
+                rule,
+                outputs,
+                vars,
+                ..
+            } => (rule, outputs, vars),
+            Node::Source(_) => unreachable!("sources have no command"),
+        };
+        let inputs = graph
+            .dependencies(n)
+            .into_iter()
+            .filter(|(kind, _)| *kind == Edge::Explicit)
+            .flat_map(|(_, d)| graph[d].explicit_files())
+            .collect::<Vec<_>>();
+        let scope = Scope {
+            rule,
+            vars,
+            inputs: inputs.clone(),
+            outputs: outputs.iter().map(|o| o.as_str()).collect(),
+        };
+        let expand = |name: &str| scope.get(name, 0).filter(|v| !v.is_empty());
+        let command = scope.get("command", 0).unwrap_or_default();
+        Self {
+            description: expand("description").unwrap_or_else(|| command.clone()),
+            command,
+            outputs: graph[n].files().into_iter().map(|f| f.to_owned()).collect(),
+            depfile: expand("depfile"),
+            rspfile: expand("rspfile")
+                .map(|path| (path, scope.get("rspfile_content", 0).unwrap_or_default())),
+            inputs: inputs.into_iter().map(|i| i.to_owned()).collect(),
+        }
+    }
+
+    /// Creates the output directories and the response file
+    fn prepare(&self, dir: &Path) -> Result<(), ExecError> {
+        let files = self.outputs.iter().chain(self.depfile.iter());
+        for parent in files.filter_map(|f| dir.join(f).parent().map(|p| p.to_owned())) {
+            fs::create_dir_all(&parent).map_err(io_error(&parent))?;
+        }
+        if let Some((path, content)) = &self.rspfile {
+            fs::write(dir.join(path), content).map_err(io_error(path))?;
+        }
+        Ok(())
+    }
+
+    /// Removes the response file once the command succeeded
+    fn cleanup(&self, dir: &Path) -> Result<(), ExecError> {
+        if let Some((path, _)) = &self.rspfile {
+            fs::remove_file(dir.join(path)).map_err(io_error(path))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("/bin/sh");
+    shell.arg("-c").arg(command);
+    shell
+}
+
+#[cfg(windows)]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.arg("/c").arg(command);
+    shell
+}
+
+fn print_output(output: &Output) {
+    use std::io::Write;
+    let _ = std::io::stdout().write_all(&output.stdout);
+    let _ = std::io::stdout().write_all(&output.stderr);
+}
+
+/// Quotes a path for the shell when it contains special characters, as ninja does for `$in` and
+/// `$out`
+fn shell_escape(path: &str) -> std::borrow::Cow<'_, str> {
+    let is_safe = |c: char| c.is_ascii_alphanumeric() || "_+-,./".contains(c);
+    if !path.is_empty() && path.chars().all(is_safe) {
+        return path.into();
+    }
+    format!("'{}'", path.replace('\'', "'\\''")).into()
+}
+
+/// Dependencies listed in a Makefile-style depfile, as written by `gcc -MD`
+fn parse_depfile(content: &str) -> Vec<String> {
+    let content = content.replace("\\\r\n", " ").replace("\\\n", " ");
+    let mut deps = vec![];
+    for line in content.lines() {
+        let mut line_deps = vec![];
+        let mut chars = line.chars().peekable();
+        let mut token = String::new();
+        loop {
+            let c = chars.next();
+            match c {
+                Some('\\') if matches!(chars.peek(), Some(' ' | '#' | '\\')) => {
+                    token.push(chars.next().unwrap())
+                }
+                Some('$') if chars.peek() == Some(&'$') => token.push(chars.next().unwrap()),
+                Some(c) if !c.is_whitespace() => token.push(c),
+                _ => {
+                    match token.strip_suffix(':') {
+                        // Everything up to now named the targets
+                        Some(_) => line_deps.clear(),
+                        None if !token.is_empty() => line_deps.push(token.clone()),
+                        None => {}
+                    }
+                    token.clear();
+                    if c.is_none() {
+                        break;
+                    }
+                }
+            }
+        }
+        deps.extend(line_deps);
+    }
+    deps
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf, time::SystemTime};
+
+    use super::{parse_depfile, shell_escape, ExecError, Executor};
+    use crate::NinjaFile;
+
+    #[test]
+    fn depfile_dependencies() {
+        assert_eq!(
+            vec!["a.c", "my header.h", "b.h"],
+            parse_depfile("out/a.o out/a.d: a.c my\\ header.h \\\n  b.h\n")
+        );
+        assert!(parse_depfile("a.o:\n").is_empty());
+    }
+
+    #[test]
+    fn shell_escapes() {
+        assert_eq!("src/a-b_c.o", shell_escape("src/a-b_c.o"));
+        assert_eq!("'my file.c'", shell_escape("my file.c"));
+        assert_eq!("'it'\\''s.c'", shell_escape("it's.c"));
+    }
+
+    fn build_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("ninja-executor-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn executor_rebuilds_out_of_date_nodes() {
+        let dir = build_dir("rebuild");
+        fs::write(dir.join("a.txt"), "a").unwrap();
+        fs::write(dir.join("b.txt"), "b").unwrap();
+        let graph = NinjaFile::parse(
+            "rule cat\n  command = cat $in > $out\n  description = CAT $out\n\
+             build out/ab.txt: cat a.txt b.txt\n\
+             build out/all.txt: cat out/ab.txt\n\
+             default out/all.txt\n",
+        )
+        .unwrap()
+        .to_graph()
+        .unwrap();
+        let executor = Executor::new(&dir).set_jobs(2);
+
+        assert_eq!(2, executor.build(&graph).unwrap());
+        assert_eq!("ab", fs::read_to_string(dir.join("out/all.txt")).unwrap());
+        assert_eq!(0, executor.build(&graph).unwrap());
+
+        let later = SystemTime::now() + std::time::Duration::from_secs(10);
+        fs::File::options()
+            .write(true)
+            .open(dir.join("b.txt"))
+            .unwrap()
+            .set_modified(later)
+            .unwrap();
+        assert_eq!(2, executor.build(&graph).unwrap());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn executor_stops_on_failure() {
+        let dir = build_dir("failure");
+        let graph = NinjaFile::parse(
+            "rule fail\n  command = exit 1\n\
+             rule touch\n  command = touch $out\n\
+             build a: fail\n\
+             build b: touch a\n",
+        )
+        .unwrap()
+        .to_graph()
+        .unwrap();
+        let result = Executor::new(&dir).build(&graph);
+        assert!(matches!(result, Err(ExecError::Failed(out)) if out == "a"));
+        assert!(!dir.join("b").exists());
+
+        let graph = NinjaFile::parse("rule touch\n  command = touch $out\nbuild b: touch a\n")
+            .unwrap()
+            .to_graph()
+            .unwrap();
+        let result = Executor::new(&dir).build(&graph);
+        assert!(matches!(result, Err(ExecError::MissingInput(a, b)) if a == "a" && b == "b"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn executor_waits_for_running_jobs_on_error() {
+        let dir = build_dir("error");
+        // The folder of the output of `bad` cannot be created, while `slow` is still running
+        fs::write(dir.join("blocker"), "").unwrap();
+        let graph = NinjaFile::parse(
+            "rule touch\n  command = touch $out\n\
+             rule slow\n  command = sleep 0.3 && touch $out\n\
+             build quick: touch\n\
+             build slow: slow\n\
+             build blocker/bad: touch quick\n",
+        )
+        .unwrap()
+        .to_graph()
+        .unwrap();
+        let result = Executor::new(&dir).set_jobs(2).build(&graph);
+        assert!(matches!(result, Err(ExecError::Io(path, _)) if path.ends_with("blocker")));
+        assert!(dir.join("slow").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,12 +1,15 @@
 use crate::Rule;
 use daggy::{
-    petgraph::{graph::NodeReferences, visit::IntoNodeReferences, Direction, Graph},
-    Dag, NodeIndex, WouldCycle,
+    petgraph::{
+        visit::{EdgeRef, IntoNodeReferences},
+        Direction,
+    },
+    Dag, WouldCycle,
 };
+pub use daggy::NodeIndex;
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, HashMap, HashSet},
     ops::Deref,
-    path::{Path, PathBuf},
 };
 use thiserror::Error;
 use daggy::petgraph::graph::DiGraph;
@@ -14,23 +17,46 @@ use daggy::petgraph::graph::DiGraph;
 #[derive(Clone, Debug, Error)]
 pub enum GraphError {
     #[error("Dependency graph would cycle")]
-    WouldCycle(#[from] WouldCycle<()>),
+    WouldCycle(#[from] WouldCycle<Edge>),
     #[error("Path does not exist in the graph")]
     UnknownSource(String),
 }
 
 #[derive(Clone, Debug, Eq, PartialEq)]
+#[allow(clippy::large_enum_variant)]
 pub enum Node {
     Source(String),
     Generated {
         rule: Rule,
-        outputs: HashSet<String>,
-        vars: HashMap<String, String>,
+        outputs: Vec<String>,
+        /// Outputs also produced by the rule, not listed in `$out`
+        implicit_outputs: Vec<String>,
+        vars: BTreeMap<String, String>,
+        /// Targets built whenever this node is. Those are not dependencies, and usually depend on
+        /// the node itself, so they are referenced by path rather than by an edge.
+        validations: Vec<String>,
     },
 }
 
 impl Node {
-    pub fn files(&self) -> HashSet<&str> {
+    /// All files provided by the node, including implicit outputs
+    pub fn files(&self) -> Vec<&str> {
+        match self {
+            Self::Source(s) => std::iter::once::<&str>(s).collect(),
+            Self::Generated {
+                outputs,
+                implicit_outputs,
+                ..
+            } => outputs
+                .iter()
+                .chain(implicit_outputs.iter())
+                .map(|f| f.as_str())
+                .collect(),
+        }
+    }
+
+    /// Files provided by the node, as listed in `$in` of dependent nodes
+    pub fn explicit_files(&self) -> Vec<&str> {
         match self {
             Self::Source(s) => std::iter::once::<&str>(s).collect(),
             Self::Generated { outputs, .. } => outputs.iter().map(|f| f.as_str()).collect(),
@@ -38,14 +64,27 @@ impl Node {
     }
 }
 
+/// Kind of dependency between two nodes of the graph
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+pub enum Edge {
+    /// Dependency listed in `$in`
+    #[default]
+    Explicit,
+    /// Dependency not listed in `$in`, which still triggers a rebuild when changed
+    Implicit,
+    /// Dependency that only needs to be built beforehand
+    OrderOnly,
+}
+
 #[derive(Clone, Debug, Default)]
 pub struct DependencyGraph {
-    dag: Dag<Node, ()>,
+    dag: Dag<Node, Edge>,
     node_ref: HashMap<String, NodeIndex>,
+    defaults: Vec<NodeIndex>,
 }
 
 impl Deref for DependencyGraph {
-    type Target = Dag<Node, ()>;
+    type Target = Dag<Node, Edge>;
 
     fn deref(&self) -> &Self::Target {
         &self.dag
@@ -67,11 +106,20 @@ impl DependencyGraph {
         for path in files {
             self.node_ref.insert(path, n);
         }
-        return n;
+        n
     }
 
     pub fn add_dependency(&mut self, ix: NodeIndex, dep: NodeIndex) -> Result<(), GraphError> {
-        self.dag.add_edge(ix, dep, ())?;
+        self.add_dependency_kind(ix, dep, Edge::Explicit)
+    }
+
+    pub fn add_dependency_kind(
+        &mut self,
+        ix: NodeIndex,
+        dep: NodeIndex,
+        kind: Edge,
+    ) -> Result<(), GraphError> {
+        self.dag.add_edge(ix, dep, kind)?;
         Ok(())
     }
 
@@ -92,9 +140,18 @@ impl DependencyGraph {
         &mut self,
         ix: NodeIndex,
         deps: impl IntoIterator<Item = NodeIndex>,
+    ) -> Result<(), GraphError> {
+        self.add_dependencies_kind(ix, deps, Edge::Explicit)
+    }
+
+    pub fn add_dependencies_kind(
+        &mut self,
+        ix: NodeIndex,
+        deps: impl IntoIterator<Item = NodeIndex>,
+        kind: Edge,
     ) -> Result<(), GraphError> {
         for dep in deps {
-            self.dag.add_edge(ix, dep, ())?;
+            self.dag.add_edge(ix, dep, kind)?;
         }
         Ok(())
     }
@@ -110,6 +167,45 @@ impl DependencyGraph {
         Ok(())
     }
 
+    /// Node providing the given file, if any
+    pub fn node_index<P: AsRef<str>>(&self, path: P) -> Option<NodeIndex> {
+        self.node_ref.get(path.as_ref()).copied()
+    }
+
+    /// Marks the node as built by default, when no target is given to ninja
+    pub fn add_default(&mut self, ix: NodeIndex) {
+        if !self.defaults.contains(&ix) {
+            self.defaults.push(ix);
+        }
+    }
+
+    pub fn defaults(&self) -> impl Iterator<Item = &Node> {
+        self.defaults.iter().map(move |&n| &self.dag[n])
+    }
+
+    /// Direct dependencies of the node, in the order they were added
+    pub fn dependencies(&self, ix: NodeIndex) -> Vec<(Edge, NodeIndex)> {
+        let mut deps = self
+            .dag
+            .graph()
+            .edges_directed(ix, Direction::Outgoing)
+            .map(|e| (*e.weight(), e.target()))
+            .collect::<Vec<_>>();
+        deps.reverse();
+        deps
+    }
+
+    /// Direct dependents of the node
+    pub fn dependents(&self, ix: NodeIndex) -> Vec<NodeIndex> {
+        let mut dependents = self
+            .dag
+            .graph()
+            .neighbors_directed(ix, Direction::Incoming)
+            .collect::<Vec<_>>();
+        dependents.reverse();
+        dependents
+    }
+
     pub fn indices(&self) -> impl Iterator<Item = NodeIndex> {
         self.dag.graph().node_indices()
     }
@@ -118,17 +214,21 @@ impl DependencyGraph {
         self.dag.graph().node_references().map(|(_, f)| f)
     }
 
-    pub fn nodes_dependencies(&self) -> impl Iterator<Item = Context<Node>> {
+    /// Nodes with their dependencies and dependents, in topological order
+    pub fn nodes_dependencies(&self) -> impl Iterator<Item = Context<'_, Node>> {
         let graph = self.dag.graph();
-        self.indices().map(move |n| {
-            let incoming = graph
-                .neighbors_directed(n, Direction::Incoming)
-                .map(|n| graph.node_weight(n).unwrap())
-                .collect();
-            let outgoing = graph
-                .neighbors_directed(n, Direction::Outgoing)
-                .map(|n| graph.node_weight(n).unwrap())
-                .collect();
+        self.topological_order().into_iter().map(move |n| {
+            // Edges are listed in reverse order of addition
+            let mut incoming = graph
+                .edges_directed(n, Direction::Incoming)
+                .map(|e| (*e.weight(), graph.node_weight(e.source()).unwrap()))
+                .collect::<Vec<_>>();
+            incoming.reverse();
+            let mut outgoing = graph
+                .edges_directed(n, Direction::Outgoing)
+                .map(|e| (*e.weight(), graph.node_weight(e.target()).unwrap()))
+                .collect::<Vec<_>>();
+            outgoing.reverse();
             Context {
                 incoming,
                 node: graph.node_weight(n).unwrap(),
@@ -137,22 +237,69 @@ impl DependencyGraph {
         })
     }
 
-    pub fn rules(&self) -> HashSet<&Rule> {
-        self.nodes()
-            .filter_map(|n| match n {
+    /// Nodes ordered such that dependencies come before their dependents. The order only depends
+    /// on the order in which nodes and dependencies were added, so that the same graph always
+    /// yields the same order.
+    pub fn topological_order(&self) -> Vec<NodeIndex> {
+        fn visit(
+            graph: &DiGraph<Node, Edge, u32>,
+            n: NodeIndex,
+            visited: &mut HashSet<NodeIndex>,
+            order: &mut Vec<NodeIndex>,
+        ) {
+            if !visited.insert(n) {
+                return;
+            }
+            let mut deps = graph
+                .neighbors_directed(n, Direction::Outgoing)
+                .collect::<Vec<_>>();
+            // Neighbors are listed in reverse order of addition
+            deps.reverse();
+            for dep in deps {
+                visit(graph, dep, visited, order);
+            }
+            order.push(n);
+        }
+
+        let graph = self.dag.graph();
+        let mut visited = HashSet::new();
+        let mut order = Vec::with_capacity(graph.node_count());
+        for n in graph.node_indices() {
+            visit(graph, n, &mut visited, &mut order);
+        }
+        order
+    }
+
+    /// Rules used by the graph, in the topological order of their first use
+    pub fn rules(&self) -> Vec<&Rule> {
+        let mut seen = HashSet::new();
+        self.topological_order()
+            .into_iter()
+            .filter_map(|n| match &self.dag[n] {
                 Node::Source(_) => None,
                 Node::Generated { rule, .. } => Some(rule),
             })
+            .filter(|rule| seen.insert(&rule.name))
             .collect()
     }
 
-    pub fn into_graph(self) -> DiGraph<Node, (), u32> {
+    pub fn into_graph(self) -> DiGraph<Node, Edge, u32> {
         self.dag.into_graph()
     }
 }
 
 pub struct Context<'a, N> {
-    pub incoming: Vec<&'a N>,
+    pub incoming: Vec<(Edge, &'a N)>,
     pub node: &'a N,
-    pub outgoing: Vec<&'a N>,
+    pub outgoing: Vec<(Edge, &'a N)>,
+}
+
+impl<'a, N> Context<'a, N> {
+    /// Dependencies of the node of the given kind
+    pub fn dependencies(&self, kind: Edge) -> impl Iterator<Item = &'a N> + '_ {
+        self.outgoing
+            .iter()
+            .filter(move |(k, _)| *k == kind)
+            .map(|(_, n)| *n)
+    }
 }
@@ -1,11 +1,17 @@
 mod ast;
 mod blocks;
+mod compdb;
+mod executor;
 pub mod pretty;
 mod writer;
 mod graph;
+mod parser;
 
 pub use crate::pretty::Pretty;
 pub use ast::*;
 pub use blocks::*;
+pub use compdb::*;
+pub use executor::*;
 pub use writer::*;
 pub use graph::*;
+pub use parser::*;
@@ -0,0 +1,871 @@
+use std::collections::{BTreeMap, HashMap};
+
+use thiserror::Error;
+
+use crate::{
+    blocks::{Build, Deps, Pool, Rule},
+    graph::{DependencyGraph, Edge, GraphError, Node},
+};
+
+#[derive(Clone, Debug, Error)]
+pub enum ParseError {
+    #[error("line {line}: {message}")]
+    Syntax { line: usize, message: String },
+    #[error("Unknown rule '{0}'")]
+    UnknownRule(String),
+    #[error("Multiple builds generate '{0}'")]
+    DuplicateOutput(String),
+    #[error("Unknown default target '{0}'")]
+    UnknownDefault(String),
+    #[error(transparent)]
+    Graph(#[from] GraphError),
+}
+
+/// Top-level statement of a ninja file
+#[derive(Clone, Debug)]
+#[allow(clippy::large_enum_variant)]
+pub enum Statement {
+    /// File-level variable, with its value evaluated
+    Variable(String, String),
+    Pool(Pool),
+    /// Rule, with its variables kept unevaluated as they are expanded per build
+    Rule(Rule),
+    /// Build, with its paths and variables evaluated
+    Build(Build),
+    Default(Vec<String>),
+    Include(String),
+    Subninja(String),
+}
+
+/// Parsed ninja file. `include` and `subninja` statements are kept as-is rather than followed.
+#[derive(Clone, Debug, Default)]
+pub struct NinjaFile {
+    pub statements: Vec<Statement>,
+}
+
+impl NinjaFile {
+    pub fn parse(input: &str) -> Result<Self, ParseError> {
+        let mut parser = Parser::default();
+        let input = input.replace("\r\n", "\n");
+        let mut lines = logical_lines(&input)?.into_iter().peekable();
+        while let Some(line) = lines.next() {
+            if line.indented {
+                return Err(syntax_error(line.number, "unexpected indent"));
+            }
+            let bindings = std::iter::from_fn(|| lines.next_if(|l| l.indented)).collect();
+            parser.statement(line, bindings)?;
+        }
+        Ok(Self {
+            statements: parser.statements,
+        })
+    }
+
+    /// File-level variables, with the value they have at the end of the file
+    pub fn variables(&self) -> HashMap<&str, &str> {
+        self.statements
+            .iter()
+            .filter_map(|s| match s {
+                Statement::Variable(name, value) => Some((name.as_str(), value.as_str())),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn rules(&self) -> impl Iterator<Item = &Rule> {
+        self.statements.iter().filter_map(|s| match s {
+            Statement::Rule(rule) => Some(rule),
+            _ => None,
+        })
+    }
+
+    pub fn pools(&self) -> impl Iterator<Item = &Pool> {
+        self.statements.iter().filter_map(|s| match s {
+            Statement::Pool(pool) => Some(pool),
+            _ => None,
+        })
+    }
+
+    pub fn builds(&self) -> impl Iterator<Item = &Build> {
+        self.statements.iter().filter_map(|s| match s {
+            Statement::Build(build) => Some(build),
+            _ => None,
+        })
+    }
+
+    /// Loads the builds into a dependency graph. File-level variables used by the rules are
+    /// copied into the variables of the builds using them, as the graph has no file scope.
+    pub fn to_graph(&self) -> Result<DependencyGraph, ParseError> {
+        let file_vars = self.variables();
+        let mut rules = HashMap::new();
+        rules.insert("phony", Rule::phony());
+        let mut graph = DependencyGraph::default();
+        let mut builds = vec![];
+        for statement in &self.statements {
+            match statement {
+                Statement::Rule(rule) => {
+                    rules.insert(&rule.name, rule.clone());
+                }
+                Statement::Build(build) => {
+                    let rule = rules
+                        .get(build.rule.as_str())
+                        .ok_or_else(|| ParseError::UnknownRule(build.rule.clone()))?;
+                    if let Some(path) = build
+                        .outputs
+                        .iter()
+                        .chain(build.implicit_outputs.iter())
+                        .find(|p| graph.node_index(p).is_some())
+                    {
+                        return Err(ParseError::DuplicateOutput(path.clone()));
+                    }
+                    let mut vars = build.vars.clone();
+                    for name in rule_variables(rule) {
+                        if let (false, Some(value)) =
+                            (vars.contains_key(&name), file_vars.get(&*name))
+                        {
+                            vars.insert(name, value.to_string());
+                        }
+                    }
+                    let ix = graph.add_node(Node::Generated {
+                        rule: rule.clone(),
+                        outputs: build.outputs.clone(),
+                        implicit_outputs: build.implicit_outputs.clone(),
+                        vars,
+                        validations: build.validations.clone(),
+                    });
+                    builds.push((ix, build));
+                }
+                _ => {}
+            }
+        }
+        for (ix, build) in builds {
+            let inputs = [
+                (Edge::Explicit, &build.inputs),
+                (Edge::Implicit, &build.implicit_inputs),
+                (Edge::OrderOnly, &build.order_only_inputs),
+            ];
+            for (kind, paths) in inputs {
+                for path in paths {
+                    let dep = match graph.node_index(path) {
+                        Some(dep) => dep,
+                        None => graph.add_source(path),
+                    };
+                    graph.add_dependency_kind(ix, dep, kind)?;
+                }
+            }
+        }
+        for statement in &self.statements {
+            if let Statement::Default(paths) = statement {
+                for path in paths {
+                    let ix = graph
+                        .node_index(path)
+                        .ok_or_else(|| ParseError::UnknownDefault(path.clone()))?;
+                    graph.add_default(ix);
+                }
+            }
+        }
+        Ok(graph)
+    }
+}
+
+/// Variables referenced by the rule, except the ones ninja provides for each build
+fn rule_variables(rule: &Rule) -> Vec<String> {
+    let values = [
+        Some(&rule.command),
+        rule.description.as_ref(),
+        rule.depfile.as_ref(),
+        rule.msvc_deps_prefix.as_ref(),
+        rule.pool.as_ref(),
+        rule.rspfile.as_ref(),
+        rule.rspfile_content.as_ref(),
+        rule.dyndep.as_ref(),
+    ];
+    let mut names = vec![];
+    for value in values.iter().flatten() {
+        let value = match Cursor::new(value, 0).eval_string(false) {
+            Ok(value) => value,
+            Err(_) => continue,
+        };
+        for piece in value.pieces {
+            match piece {
+                Piece::Variable(name)
+                    if !["in", "in_newline", "out"].contains(&name.as_str())
+                        && !names.contains(&name) =>
+                {
+                    names.push(name)
+                }
+                _ => {}
+            }
+        }
+    }
+    names
+}
+
+/// Expands the variables of an unevaluated string, such as a rule command
+pub(crate) fn evaluate(
+    text: &str,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> Result<String, ParseError> {
+    Ok(Cursor::new(text, 0).eval_string(false)?.evaluate(lookup))
+}
+
+fn syntax_error<S: Into<String>>(line: usize, message: S) -> ParseError {
+    ParseError::Syntax {
+        line,
+        message: message.into(),
+    }
+}
+
+/// Line with continuations joined and comments removed
+#[derive(Clone, Debug)]
+struct Line {
+    number: usize,
+    indented: bool,
+    text: String,
+}
+
+fn logical_lines(input: &str) -> Result<Vec<Line>, ParseError> {
+    let mut lines = vec![];
+    let mut chars = input.chars().peekable();
+    let mut number = 1;
+    while chars.peek().is_some() {
+        let start = number;
+        let mut indented = false;
+        while chars.next_if_eq(&' ').is_some() {
+            indented = true;
+        }
+        match chars.peek() {
+            Some('\t') => return Err(syntax_error(number, "tabs are not allowed, use spaces")),
+            Some('#') => {
+                chars.find(|&c| c == '\n');
+                number += 1;
+                continue;
+            }
+            _ => {}
+        }
+        let mut text = String::new();
+        while let Some(c) = chars.next() {
+            match c {
+                '\n' => break,
+                '$' => match chars.next() {
+                    Some('\n') => {
+                        number += 1;
+                        while chars.next_if_eq(&' ').is_some() {}
+                    }
+                    Some(c) => {
+                        text.push('$');
+                        text.push(c);
+                    }
+                    None => text.push('$'),
+                },
+                c => text.push(c),
+            }
+        }
+        number += 1;
+        if !text.trim().is_empty() {
+            lines.push(Line {
+                number: start,
+                indented,
+                text,
+            });
+        }
+    }
+    Ok(lines)
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Piece {
+    Literal(String),
+    Variable(String),
+}
+
+/// String with its variable references, before evaluation
+#[derive(Clone, Debug, Default)]
+struct EvalString {
+    pieces: Vec<Piece>,
+    /// Text as written in the file
+    raw: String,
+}
+
+impl EvalString {
+    /// Expands variables, undefined variables are empty
+    fn evaluate(&self, lookup: impl Fn(&str) -> Option<String>) -> String {
+        self.pieces
+            .iter()
+            .map(|piece| match piece {
+                Piece::Literal(s) => s.clone(),
+                Piece::Variable(name) => lookup(name).unwrap_or_default(),
+            })
+            .collect()
+    }
+}
+
+fn is_simple_var_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '-'
+}
+
+fn is_ident_char(c: char) -> bool {
+    is_simple_var_char(c) || c == '.'
+}
+
+struct Cursor {
+    line: usize,
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Cursor {
+    fn new(text: &str, line: usize) -> Self {
+        Self {
+            line,
+            chars: text.chars().collect(),
+            pos: 0,
+        }
+    }
+
+    fn error<S: Into<String>>(&self, message: S) -> ParseError {
+        syntax_error(self.line, message)
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn skip_spaces(&mut self) {
+        while self.peek() == Some(' ') {
+            self.pos += 1;
+        }
+    }
+
+    fn take_while(&mut self, f: impl Fn(char) -> bool) -> String {
+        let start = self.pos;
+        while self.peek().is_some_and(&f) {
+            self.pos += 1;
+        }
+        self.chars[start..self.pos].iter().collect()
+    }
+
+    fn ident(&mut self, what: &str) -> Result<String, ParseError> {
+        self.skip_spaces();
+        let ident = self.take_while(is_ident_char);
+        if ident.is_empty() {
+            return Err(self.error(format!("expected {}", what)));
+        }
+        Ok(ident)
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), ParseError> {
+        self.skip_spaces();
+        if self.peek() != Some(c) {
+            return Err(self.error(format!("expected '{}'", c)));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn end(&mut self) -> Result<(), ParseError> {
+        self.skip_spaces();
+        match self.peek() {
+            None => Ok(()),
+            Some(c) => Err(self.error(format!("unexpected '{}'", c))),
+        }
+    }
+
+    /// Consumes one of the `|`, `||` and `|@` separators of build statements
+    fn separator(&mut self) -> Option<&'static str> {
+        self.skip_spaces();
+        if self.peek() != Some('|') {
+            return None;
+        }
+        self.pos += 1;
+        let sep = match self.peek() {
+            Some('|') => "||",
+            Some('@') => "|@",
+            _ => return Some("|"),
+        };
+        self.pos += 1;
+        Some(sep)
+    }
+
+    /// Reads a string up to the end of the line, or up to the next unescaped space, colon or pipe
+    /// for paths.
+    fn eval_string(&mut self, path: bool) -> Result<EvalString, ParseError> {
+        let start = self.pos;
+        let mut pieces = vec![];
+        let mut literal = String::new();
+        while let Some(c) = self.peek() {
+            if path && [' ', ':', '|'].contains(&c) {
+                break;
+            }
+            self.pos += 1;
+            if c != '$' {
+                literal.push(c);
+                continue;
+            }
+            let name = match self.peek() {
+                Some(c @ ('$' | ' ' | ':')) => {
+                    self.pos += 1;
+                    literal.push(c);
+                    continue;
+                }
+                Some('{') => {
+                    self.pos += 1;
+                    let name = self.take_while(is_ident_char);
+                    if name.is_empty() || self.peek() != Some('}') {
+                        return Err(self.error("bad variable name in '${...}'"));
+                    }
+                    self.pos += 1;
+                    name
+                }
+                Some(c) if is_simple_var_char(c) => self.take_while(is_simple_var_char),
+                _ => return Err(self.error("bad $-escape (literal $ must be written as $$)")),
+            };
+            if !literal.is_empty() {
+                pieces.push(Piece::Literal(std::mem::take(&mut literal)));
+            }
+            pieces.push(Piece::Variable(name));
+        }
+        if !literal.is_empty() {
+            pieces.push(Piece::Literal(literal));
+        }
+        Ok(EvalString {
+            pieces,
+            raw: self.chars[start..self.pos].iter().collect(),
+        })
+    }
+
+    /// Reads paths up to the end of the line or the next separator
+    fn paths(&mut self) -> Result<Vec<EvalString>, ParseError> {
+        let mut paths = vec![];
+        loop {
+            self.skip_spaces();
+            match self.peek() {
+                None | Some(':') | Some('|') => return Ok(paths),
+                _ => paths.push(self.eval_string(true)?),
+            }
+        }
+    }
+
+    /// Reads a `name = value` binding
+    fn binding(&mut self) -> Result<(String, EvalString), ParseError> {
+        let name = self.ident("a variable name")?;
+        self.expect('=')?;
+        self.skip_spaces();
+        let value = self.eval_string(false)?;
+        Ok((name, value))
+    }
+}
+
+#[derive(Default)]
+struct Parser {
+    /// File-level variables, evaluated
+    env: HashMap<String, String>,
+    statements: Vec<Statement>,
+}
+
+impl Parser {
+    fn lookup(&self) -> impl Fn(&str) -> Option<String> + '_ {
+        move |name| self.env.get(name).cloned()
+    }
+
+    fn statement(&mut self, line: Line, bindings: Vec<Line>) -> Result<(), ParseError> {
+        let mut cursor = Cursor::new(&line.text, line.number);
+        let keyword = cursor.ident("a statement")?;
+        let bindings = bindings
+            .into_iter()
+            .map(|l| {
+                let mut cursor = Cursor::new(&l.text, l.number);
+                let (name, value) = cursor.binding()?;
+                Ok((l.number, name, value))
+            })
+            .collect::<Result<Vec<_>, ParseError>>()?;
+        let takes_bindings = ["rule", "build", "pool"].contains(&keyword.as_str());
+        if let (false, Some((number, _, _))) = (takes_bindings, bindings.first()) {
+            return Err(syntax_error(*number, "unexpected indent"));
+        }
+        let statement = match keyword.as_str() {
+            "rule" => {
+                let name = cursor.ident("a rule name")?;
+                cursor.end()?;
+                Statement::Rule(self.rule(line.number, name, bindings)?)
+            }
+            "build" => Statement::Build(self.build(cursor, bindings)?),
+            "pool" => {
+                let name = cursor.ident("a pool name")?;
+                cursor.end()?;
+                let mut depth = None;
+                for (number, var, value) in bindings {
+                    if var != "depth" {
+                        return Err(syntax_error(
+                            number,
+                            format!("unexpected variable '{}'", var),
+                        ));
+                    }
+                    let value = value.evaluate(self.lookup());
+                    depth = Some(value.parse().map_err(|_| {
+                        syntax_error(number, format!("invalid pool depth '{}'", value))
+                    })?);
+                }
+                let depth = depth.ok_or_else(|| syntax_error(line.number, "expected 'depth ='"))?;
+                Statement::Pool(Pool::new(name, depth))
+            }
+            "default" => {
+                let paths = self.evaluate_paths(cursor.paths()?, &self.lookup());
+                cursor.end()?;
+                if paths.is_empty() {
+                    return Err(cursor.error("expected a target name"));
+                }
+                Statement::Default(paths)
+            }
+            "include" | "subninja" => {
+                cursor.skip_spaces();
+                let path = cursor.eval_string(true)?.evaluate(self.lookup());
+                cursor.end()?;
+                if path.is_empty() {
+                    return Err(cursor.error("expected a path"));
+                }
+                if keyword == "include" {
+                    Statement::Include(path)
+                } else {
+                    Statement::Subninja(path)
+                }
+            }
+            _ => {
+                cursor.expect('=')?;
+                cursor.skip_spaces();
+                let value = cursor.eval_string(false)?.evaluate(self.lookup());
+                self.env.insert(keyword.clone(), value.clone());
+                Statement::Variable(keyword, value)
+            }
+        };
+        self.statements.push(statement);
+        Ok(())
+    }
+
+    fn rule(
+        &self,
+        number: usize,
+        name: String,
+        bindings: Vec<(usize, String, EvalString)>,
+    ) -> Result<Rule, ParseError> {
+        let mut command = None;
+        let mut rule = Rule::new(name, String::new());
+        for (number, var, EvalString { raw, .. }) in bindings {
+            match var.as_str() {
+                "command" => command = Some(raw),
+                "description" => rule.description = Some(raw),
+                "depfile" => rule.depfile = Some(raw),
+                "deps" => {
+                    rule.deps = match raw.as_str() {
+                        "gcc" => Some(Deps::Gcc),
+                        "msvc" => Some(Deps::Msvc),
+                        _ => {
+                            return Err(syntax_error(
+                                number,
+                                format!("unknown deps type '{}'", raw),
+                            ))
+                        }
+                    }
+                }
+                "msvc_deps_prefix" => rule.msvc_deps_prefix = Some(raw),
+                "pool" => rule.pool = Some(raw),
+                "restat" => rule.restat = !raw.is_empty(),
+                "generator" => rule.generator = !raw.is_empty(),
+                "rspfile" => rule.rspfile = Some(raw),
+                "rspfile_content" => rule.rspfile_content = Some(raw),
+                "dyndep" => rule.dyndep = Some(raw),
+                _ => {
+                    return Err(syntax_error(
+                        number,
+                        format!("unexpected variable '{}'", var),
+                    ))
+                }
+            }
+        }
+        rule.command = command.ok_or_else(|| syntax_error(number, "expected 'command ='"))?;
+        Ok(rule)
+    }
+
+    fn build(
+        &self,
+        mut cursor: Cursor,
+        bindings: Vec<(usize, String, EvalString)>,
+    ) -> Result<Build, ParseError> {
+        let outputs = cursor.paths()?;
+        if outputs.is_empty() {
+            return Err(cursor.error("expected a path"));
+        }
+        let implicit_outputs = match cursor.separator() {
+            Some("|") => cursor.paths()?,
+            Some(sep) => return Err(cursor.error(format!("unexpected '{}'", sep))),
+            None => vec![],
+        };
+        cursor.expect(':')?;
+        let rule = cursor.ident("a rule name")?;
+        let inputs = cursor.paths()?;
+        let mut sep = cursor.separator();
+        let mut section = |name: &str, sep: &mut Option<&str>| -> Result<_, ParseError> {
+            if *sep != Some(name) {
+                return Ok(vec![]);
+            }
+            let paths = cursor.paths()?;
+            *sep = cursor.separator();
+            Ok(paths)
+        };
+        let implicit_inputs = section("|", &mut sep)?;
+        let order_only_inputs = section("||", &mut sep)?;
+        let validations = section("|@", &mut sep)?;
+        if let Some(sep) = sep {
+            return Err(cursor.error(format!("unexpected '{}'", sep)));
+        }
+        cursor.end()?;
+
+        // Build variables are evaluated in order, in the file scope along with the earlier
+        // variables of the build. Paths may also use build variables.
+        let mut vars = BTreeMap::new();
+        for (_, name, value) in bindings {
+            let value = value.evaluate(|n: &str| vars.get(n).cloned().or_else(|| self.lookup()(n)));
+            vars.insert(name, value);
+        }
+        let lookup = |name: &str| vars.get(name).cloned().or_else(|| self.lookup()(name));
+        Ok(Build {
+            rule,
+            outputs: self.evaluate_paths(outputs, &lookup),
+            implicit_outputs: self.evaluate_paths(implicit_outputs, &lookup),
+            inputs: self.evaluate_paths(inputs, &lookup),
+            implicit_inputs: self.evaluate_paths(implicit_inputs, &lookup),
+            order_only_inputs: self.evaluate_paths(order_only_inputs, &lookup),
+            validations: self.evaluate_paths(validations, &lookup),
+            vars: vars.clone(),
+        })
+    }
+
+    fn evaluate_paths(
+        &self,
+        paths: Vec<EvalString>,
+        lookup: &impl Fn(&str) -> Option<String>,
+    ) -> Vec<String> {
+        paths.into_iter().map(|p| p.evaluate(lookup)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use crate::{Build, Deps, Edge, Node, ParseError, Pool, Rule, Statement, Writer};
+
+    use super::NinjaFile;
+
+    fn render(writer: Writer) -> String {
+        let mut out = vec![];
+        writer
+            .content_builder
+            .into_doc()
+            .render(80, &mut out)
+            .unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn parse_lexing_rules() {
+        let file = NinjaFile::parse(
+            "# comment $\n\
+             cflags = -O2\n\
+             cflags = $cflags -Wall\n\
+             \n\
+             rule cc\n  \
+               command = gcc $cflags $\n      -c $in -o $out\n  \
+               # comment in a rule\n  \
+               deps = gcc\n  \
+               restat = 1\n\
+             build foo$ bar.o: cc foo$ bar.c$:x | ${dir}/h.h\n  \
+               dir = include\n  \
+               cflags = $cflags -g\n\
+             default foo$ bar.o\n",
+        )
+        .unwrap();
+        assert_eq!(Some(&"-O2 -Wall"), file.variables().get("cflags"));
+        let rule = file.rules().next().unwrap();
+        assert_eq!("gcc $cflags -c $in -o $out", rule.command);
+        assert_eq!(Some(Deps::Gcc), rule.deps);
+        assert!(rule.restat);
+        let build = file.builds().next().unwrap();
+        assert_eq!(vec!["foo bar.o"], build.outputs);
+        assert_eq!(vec!["foo bar.c:x"], build.inputs);
+        assert_eq!(vec!["include/h.h"], build.implicit_inputs);
+        assert_eq!("-O2 -Wall -g", build.vars["cflags"]);
+        assert!(
+            matches!(file.statements.last(), Some(Statement::Default(d)) if d == &["foo bar.o"])
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        let error = |input: &str| match NinjaFile::parse(input) {
+            Err(ParseError::Syntax { line, .. }) => line,
+            other => panic!("expected a syntax error, got {:?}", other),
+        };
+        assert_eq!(2, error("a = 1\n  b = 2\n"));
+        assert_eq!(1, error("rule cc\n  description = CC\n"));
+        assert_eq!(
+            3,
+            error("rule cc\n  command = cc\nbuild a: cc b || c | d\n")
+        );
+        assert_eq!(1, error("a = $\n  b $!\n"));
+        assert_eq!(2, error("pool p\n  depth = many\n"));
+    }
+
+    #[test]
+    fn writer_round_trip() {
+        let ccrule = Rule::new("cc".to_owned(), "gcc -c $in -o $out $flags".to_owned())
+            .set_depfile("$out.d".to_owned())
+            .set_deps(Deps::Gcc)
+            .set_pool("link_pool".to_owned())
+            .set_generator(true);
+        let mut build = Build::new(
+            ccrule.get_ref(),
+            vec!["out dir/a:b.o".to_owned()],
+            vec!["src/$weird name.c".to_owned()],
+        );
+        build.implicit_outputs = vec!["a.d".to_owned()];
+        build.implicit_inputs = vec!["config.h".to_owned()];
+        build.order_only_inputs = vec!["gen erated.h".to_owned()];
+        build.validations = vec!["check".to_owned()];
+        build
+            .vars
+            .insert("flags".to_owned(), " -DX=\"$HOME\" ".to_owned());
+
+        let mut writer = Writer::new();
+        writer.add_variable("builddir", " build dir");
+        writer.add_pool(&Pool::new("link_pool", 4));
+        writer.add_rule(&ccrule);
+        writer.add_build(&build).unwrap();
+        writer.add_default("out dir/a:b.o".to_owned()).unwrap();
+        writer.add_include("rules $1.ninja").unwrap();
+        writer.add_subninja("sub.ninja").unwrap();
+        let file = NinjaFile::parse(&render(writer)).unwrap();
+
+        assert_eq!(Some(&" build dir"), file.variables().get("builddir"));
+        let rule = file.rules().next().unwrap();
+        assert_eq!(ccrule.command, rule.command);
+        assert_eq!(ccrule.depfile, rule.depfile);
+        assert_eq!(ccrule.deps, rule.deps);
+        assert_eq!(ccrule.pool, rule.pool);
+        assert_eq!(ccrule.generator, rule.generator);
+        let parsed = file.builds().next().unwrap();
+        assert_eq!(build.outputs, parsed.outputs);
+        assert_eq!(build.implicit_outputs, parsed.implicit_outputs);
+        assert_eq!(build.inputs, parsed.inputs);
+        assert_eq!(build.implicit_inputs, parsed.implicit_inputs);
+        assert_eq!(build.order_only_inputs, parsed.order_only_inputs);
+        assert_eq!(build.validations, parsed.validations);
+        assert_eq!(build.vars, parsed.vars);
+        let paths = file
+            .statements
+            .iter()
+            .filter_map(|s| match s {
+                Statement::Pool(p) => Some(format!("pool {} {}", p.name(), p.depth())),
+                Statement::Default(d) => Some(format!("default {}", d.join(","))),
+                Statement::Include(p) => Some(format!("include {}", p)),
+                Statement::Subninja(p) => Some(format!("subninja {}", p)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                "pool link_pool 4",
+                "default out dir/a:b.o",
+                "include rules $1.ninja",
+                "subninja sub.ninja"
+            ],
+            paths
+        );
+    }
+
+    #[test]
+    fn build_variables_see_earlier_ones() {
+        let file = NinjaFile::parse(
+            "a = 0\n\
+             rule cc\n  command = cc $a $b\n\
+             build x.o: cc x.c\n  b = $a 2\n  a = 1\n  c = $a $b\n",
+        )
+        .unwrap();
+        let build = file.builds().next().unwrap();
+        assert_eq!(Some(&"0 2".to_owned()), build.vars.get("b"));
+        assert_eq!(Some(&"1 0 2".to_owned()), build.vars.get("c"));
+    }
+
+    #[test]
+    fn writer_round_trip_included_rules() {
+        let input = "include rules.ninja\n\
+                     build a.o: cc a.c\n\
+                     subninja sub.ninja\n\
+                     build b.o: cxx b.cpp\n";
+        let file = NinjaFile::parse(input).unwrap();
+        let mut writer = Writer::new();
+        writer.add_ninja_file(&file).unwrap();
+        let parsed = NinjaFile::parse(&render(writer)).unwrap();
+        assert_eq!(format!("{:?}", file.statements), format!("{:?}", parsed.statements));
+    }
+
+    #[test]
+    fn graph_round_trip() {
+        let input = "cflags = -O2\n\
+                     rule cc\n  command = gcc $cflags -c $in -o $out\n\n\
+                     rule ld\n  command = gcc $in -o $out\n\n\
+                     build a.o: cc a.c | a.h || gen\n\
+                     build app: ld a.o\n\
+                     build gen: phony\n\
+                     default app\n";
+        let graph = NinjaFile::parse(input).unwrap().to_graph().unwrap();
+        let object = graph.node_index("a.o").unwrap();
+        match &graph[object] {
+            Node::Generated { vars, .. } => {
+                let mut expected = BTreeMap::new();
+                expected.insert("cflags".to_owned(), "-O2".to_owned());
+                assert_eq!(&expected, vars);
+            }
+            Node::Source(_) => panic!("a.o should be generated"),
+        }
+        let context = graph
+            .nodes_dependencies()
+            .find(|c| c.node == &graph[object])
+            .unwrap();
+        let files = |kind| {
+            context
+                .dependencies(kind)
+                .flat_map(|n| n.files())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(vec!["a.c"], files(Edge::Explicit));
+        assert_eq!(vec!["a.h"], files(Edge::Implicit));
+        assert_eq!(vec!["gen"], files(Edge::OrderOnly));
+        assert_eq!(
+            vec!["app"],
+            graph.defaults().flat_map(|n| n.files()).collect::<Vec<_>>()
+        );
+
+        let mut writer = Writer::new();
+        writer.add_graph(&graph).unwrap();
+        let written = render(writer);
+        assert!(!written.contains("rule phony"));
+        let reparsed = NinjaFile::parse(&written).unwrap().to_graph().unwrap();
+        assert_eq!(graph.node_count(), reparsed.node_count());
+        assert_eq!(graph.edge_count(), reparsed.edge_count());
+    }
+
+    #[test]
+    fn graph_errors() {
+        let error = |input: &str| NinjaFile::parse(input).unwrap().to_graph().unwrap_err();
+        assert!(matches!(error("build a: cc b\n"), ParseError::UnknownRule(r) if r == "cc"));
+        assert!(matches!(
+            error("build a: phony\nbuild a: phony\n"),
+            ParseError::DuplicateOutput(p) if p == "a"
+        ));
+        assert!(matches!(error("default a\n"), ParseError::UnknownDefault(p) if p == "a"));
+        assert!(matches!(
+            error("build a: phony b\nbuild b: phony a\n"),
+            ParseError::Graph(_)
+        ));
+    }
+}
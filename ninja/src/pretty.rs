@@ -42,3 +42,60 @@ where
         .append(allocator.space())
         .append(value.into())
 }
+
+/// Escapes a path for use in `build`, `default`, `include` and `subninja` statements, where spaces
+/// separate paths and colons separate outputs from the rule. Ninja cannot represent newlines in
+/// paths; they are escaped as line continuations and therefore dropped. It cannot escape `|`
+/// either, which the [`Writer`](crate::Writer) rejects.
+pub fn escape_path(path: &str) -> Cow<'_, str> {
+    if !path.contains(['$', ' ', ':', '\n']) {
+        return Cow::Borrowed(path);
+    }
+    let mut escaped = String::with_capacity(path.len() + 2);
+    for c in path.chars() {
+        if matches!(c, '$' | ' ' | ':' | '\n') {
+            escaped.push('$');
+        }
+        escaped.push(c);
+    }
+    Cow::Owned(escaped)
+}
+
+/// Escapes the value of a variable binding so that it is read back literally. Only the leading
+/// space needs escaping, as ninja otherwise strips it. Newlines are escaped as line continuations
+/// and therefore dropped.
+pub fn escape_value(value: &str) -> Cow<'_, str> {
+    if !value.starts_with(' ') && !value.contains(['$', '\n']) {
+        return Cow::Borrowed(value);
+    }
+    let mut escaped = String::with_capacity(value.len() + 2);
+    for (i, c) in value.chars().enumerate() {
+        if matches!(c, '$' | '\n') || (i == 0 && c == ' ') {
+            escaped.push('$');
+        }
+        escaped.push(c);
+    }
+    Cow::Owned(escaped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{escape_path, escape_value};
+
+    #[test]
+    fn escape_paths() {
+        assert_eq!("src/main.c", escape_path("src/main.c"));
+        assert_eq!("my$ project/main.c", escape_path("my project/main.c"));
+        assert_eq!("C$:\\src\\main.c", escape_path("C:\\src\\main.c"));
+        assert_eq!("cost$$.c", escape_path("cost$.c"));
+        assert_eq!("a$\nb.c", escape_path("a\nb.c"));
+    }
+
+    #[test]
+    fn escape_values() {
+        assert_eq!("-DFOO=1 -I../a b", escape_value("-DFOO=1 -I../a b"));
+        assert_eq!("-DPRICE=$$5 C:\\", escape_value("-DPRICE=$5 C:\\"));
+        assert_eq!("$  two", escape_value("  two"));
+        assert_eq!("a$\nb", escape_value("a\nb"));
+    }
+}
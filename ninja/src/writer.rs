@@ -3,25 +3,46 @@ use std::io::stdout;
 use std::{collections::HashSet, fs::File};
 
 use pretty::{BuildDoc, DocBuilder, RcAllocator, RcDoc};
+use thiserror::Error;
 
 use crate::{
-    ast::NinjaAst,
     blocks::Build,
+    blocks::Pool,
     blocks::Rule,
-    pretty::{Annotation, Pretty},
+    pretty::{escape_path, escape_value, variable, Annotation, Pretty},
 };
-use crate::graph::{Node, DependencyGraph};
-use daggy::Dag;
+use crate::graph::{DependencyGraph, Edge, Node};
+use crate::parser::{NinjaFile, Statement};
+
+#[derive(Debug, Error)]
+pub enum WriteError {
+    #[error("Path '{0}' contains '|', which ninja cannot read in a path")]
+    UnsupportedPath(String),
+}
+
+/// Checks that the paths can be written, `|` being read by ninja as the separator of implicit and
+/// order-only dependencies without any way to escape it
+fn check_paths<'p, I: IntoIterator<Item = &'p String>>(paths: I) -> Result<(), WriteError> {
+    match paths.into_iter().find(|p| p.contains('|')) {
+        Some(path) => Err(WriteError::UnsupportedPath(path.clone())),
+        None => Ok(()),
+    }
+}
 
 pub struct Writer<'a> {
+    written_pools: HashSet<String>,
     written_rules: HashSet<String>,
-    content_builder: DocBuilder<'a, RcAllocator, Annotation>,
+    /// Whether other files were included, whose rules are not known to the writer
+    has_includes: bool,
+    pub(crate) content_builder: DocBuilder<'a, RcAllocator, Annotation>,
 }
 
 impl<'a> Default for Writer<'a> {
     fn default() -> Self {
         Self {
+            written_pools: HashSet::new(),
             written_rules: HashSet::new(),
+            has_includes: false,
             content_builder: DocBuilder(&RcAllocator, BuildDoc::from(RcDoc::nil())),
         }
     }
@@ -32,11 +53,68 @@ impl<'a> Writer<'a> {
         Self::default()
     }
 
+    /// Declares a file-level variable, such as `builddir` or `ninja_required_version`. Variables
+    /// are visible to everything written after them.
+    pub fn add_variable<S: Into<String>, V: Into<String>>(&mut self, name: S, value: V) {
+        let alloc = &RcAllocator;
+        self.content_builder = self
+            .content_builder
+            .clone()
+            .append(variable(
+                name.into(),
+                alloc.text(escape_value(&value.into()).into_owned()),
+                alloc,
+            ))
+            .append(alloc.hardline());
+    }
+
+    /// Declares a pool. Pools need to be declared before the rules and builds using them.
+    pub fn add_pool(&mut self, pool: &Pool) {
+        if self.written_pools.contains(&pool.name) {
+            return;
+        }
+        self.written_pools.insert(pool.name.clone());
+        self.content_builder = self
+            .content_builder
+            .clone()
+            .append(pool.pretty(&RcAllocator))
+            .append(RcAllocator.hardline());
+    }
+
+    /// Includes another ninja file in the current scope, its rules and variables become visible
+    /// to this file.
+    pub fn add_include<S: Into<String>>(&mut self, path: S) -> Result<(), WriteError> {
+        self.add_file_reference("include", path.into())
+    }
+
+    /// Includes another ninja file in a child scope, its rules and variables stay local to it.
+    pub fn add_subninja<S: Into<String>>(&mut self, path: S) -> Result<(), WriteError> {
+        self.add_file_reference("subninja", path.into())
+    }
+
+    fn add_file_reference(&mut self, keyword: &'static str, path: String) -> Result<(), WriteError> {
+        check_paths([&path])?;
+        self.has_includes = true;
+        let alloc = &RcAllocator;
+        self.content_builder = self
+            .content_builder
+            .clone()
+            .append(alloc.text(keyword).annotate(Annotation::Keyword))
+            .append(alloc.space())
+            .append(escape_path(&path).into_owned())
+            .append(alloc.hardline());
+        Ok(())
+    }
+
     pub fn add_rule(&mut self, rule: &Rule) {
         if self.written_rules.contains(&rule.name) {
             return;
         }
         self.written_rules.insert(rule.name.clone());
+        // Built into ninja, declaring it is an error
+        if rule.is_phony() {
+            return;
+        }
         self.content_builder = self
             .content_builder
             .clone()
@@ -44,60 +122,47 @@ impl<'a> Writer<'a> {
             .append(RcAllocator.hardline());
     }
 
-    pub fn add_build(&mut self, build: &Build) {
-        assert!(self.written_rules.contains(&build.rule));
+    pub fn add_build(&mut self, build: &Build) -> Result<(), WriteError> {
+        check_paths(
+            build
+                .outputs
+                .iter()
+                .chain(&build.implicit_outputs)
+                .chain(&build.inputs)
+                .chain(&build.implicit_inputs)
+                .chain(&build.order_only_inputs)
+                .chain(&build.validations),
+        )?;
+        // Rules may be declared by the included files
+        assert!(
+            self.has_includes || self.written_rules.contains(&build.rule),
+            "Rule {} of build {} is not declared",
+            build.rule,
+            build.outputs.join(" ")
+        );
 
         self.content_builder = self
             .content_builder
             .clone()
             .append(build.pretty(&RcAllocator))
             .append(RcAllocator.hardline());
+        Ok(())
     }
 
-    pub fn add_default(&mut self, default: String) {
+    pub fn add_default(&mut self, default: String) -> Result<(), WriteError> {
+        check_paths([&default])?;
         let alloc = &RcAllocator;
         self.content_builder = self
             .content_builder
             .clone()
-            .append("default")
-            .annotate(Annotation::Keyword)
+            .append(alloc.text("default").annotate(Annotation::Keyword))
             .append(alloc.space())
-            .append(default)
+            .append(escape_path(&default).into_owned())
             .append(alloc.hardline());
+        Ok(())
     }
 
-    pub fn add_ast<A: Into<NinjaAst>>(&mut self, ast: A) -> Option<String> {
-        use NinjaAst::*;
-        match ast.into() {
-            Source(f) => return Some(f),
-            Generated {
-                name,
-                rule,
-                deps,
-                vars,
-            } => {
-                let inputs = deps
-                    .into_iter()
-                    .map(|a| self.add_ast(a))
-                    .filter_map(id)
-                    .collect();
-                self.add_rule(&rule);
-                self.add_build(&{
-                    let mut b = Build::new(rule.get_ref(), vec![name.clone()], inputs);
-                    b.vars = vars;
-                    b
-                });
-                Some(name)
-            }
-            Default(inner) => {
-                let name = self.add_ast(*inner).unwrap();
-                self.add_default(name);
-                None
-            }
-        }
-    }
-
-    pub fn add_graph(&mut self, graph: &DependencyGraph) {
+    pub fn add_graph(&mut self, graph: &DependencyGraph) -> Result<(), WriteError> {
         for rule in graph.rules() {
             self.add_rule(rule);
         }
@@ -108,18 +173,59 @@ impl<'a> Writer<'a> {
                 Node::Generated {
                     rule,
                     outputs,
+                    implicit_outputs,
                     vars,
+                    validations,
                 } => {
+                    let files = |kind| {
+                        nctx.dependencies(kind)
+                            .flat_map(|n| n.explicit_files().into_iter())
+                            .map(|f| f.to_string())
+                            .collect()
+                    };
                     let build = Build {
                         vars,
                         rule: rule.name,
-                        inputs: nctx.outgoing.iter().flat_map(|n| n.files().into_iter()).map(|f| f.to_string()).collect(),
+                        inputs: files(Edge::Explicit),
+                        implicit_inputs: files(Edge::Implicit),
+                        order_only_inputs: files(Edge::OrderOnly),
+                        validations,
                         outputs: outputs.into_iter().collect(),
+                        implicit_outputs: implicit_outputs.into_iter().collect(),
                     };
-                    self.add_build(&build);
+                    self.add_build(&build)?;
+                }
+            }
+        }
+
+        for node in graph.defaults() {
+            for file in node.explicit_files() {
+                self.add_default(file.to_owned())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes back the statements of a parsed file
+    pub fn add_ninja_file(&mut self, file: &NinjaFile) -> Result<(), WriteError> {
+        // Builds may use the built-in phony rule, which is never declared
+        self.add_rule(&Rule::phony());
+        for statement in &file.statements {
+            match statement {
+                Statement::Variable(name, value) => self.add_variable(name.clone(), value.clone()),
+                Statement::Pool(pool) => self.add_pool(pool),
+                Statement::Rule(rule) => self.add_rule(rule),
+                Statement::Build(build) => self.add_build(build)?,
+                Statement::Default(paths) => {
+                    for path in paths {
+                        self.add_default(path.clone())?;
+                    }
                 }
+                Statement::Include(path) => self.add_include(path.clone())?,
+                Statement::Subninja(path) => self.add_subninja(path.clone())?,
             }
         }
+        Ok(())
     }
 
     pub fn write_tty(self, width: usize) -> std::io::Result<()> {
@@ -133,15 +239,11 @@ impl<'a> Writer<'a> {
     }
 }
 
-fn id<T>(x: T) -> T {
-    x
-}
-
 #[cfg(test)]
 mod tests {
-    use std::collections::HashMap;
+    use std::{collections::BTreeMap, convert::TryFrom};
 
-    use crate::{NinjaAst::*, Rule, Writer};
+    use crate::{Build, DependencyGraph, Edge, Node, NinjaAst::*, Pool, Rule, Writer};
 
     macro_rules! set {
         ($($value:expr),*) => {
@@ -167,20 +269,214 @@ mod tests {
                     name: "gen.o".to_owned(),
                     rule: ccrule.clone(),
                     deps: vec![Source("gen.c".to_owned())],
-                    vars: HashMap::new(),
+                    vars: BTreeMap::new(),
+                    implicit_outputs: vec![],
+                    implicit_deps: vec![],
+                    order_only_deps: vec![],
+                    validations: vec![],
                 },
                 Generated {
                     name: "other.o".to_owned(),
                     rule: ccrule.clone(),
                     deps: vec![Source("other.c".to_owned())],
-                    vars: HashMap::new(),
+                    vars: BTreeMap::new(),
+                    implicit_outputs: vec![],
+                    implicit_deps: vec![],
+                    order_only_deps: vec![],
+                    validations: vec![],
                 },
             ],
-            vars: HashMap::new(),
+            vars: BTreeMap::new(),
+            implicit_outputs: vec![],
+            implicit_deps: vec![],
+            order_only_deps: vec![],
+            validations: vec![],
         };
 
         let mut writer = Writer::new();
-        writer.add_ast(ast);
+        writer.add_graph(&DependencyGraph::try_from(ast).unwrap()).unwrap();
         assert_eq!(set![ccrule.name, ldrule.name], writer.written_rules);
     }
+
+    #[test]
+    fn writer_skips_written_builds() {
+        let ccrule = Rule::new("cc".to_owned(), "gcc -c $in -o $out".to_owned());
+        let arrule = Rule::new("ar".to_owned(), "ar rcs $out $in".to_owned());
+        let ldrule = Rule::new("ld".to_owned(), "gcc $in -o $out".to_owned());
+        let lib = Generated {
+            name: "libfoo.a".to_owned(),
+            rule: arrule,
+            deps: vec![Generated {
+                name: "foo.o".to_owned(),
+                rule: ccrule,
+                deps: vec![Source("foo.c".to_owned())],
+                vars: BTreeMap::new(),
+                implicit_outputs: vec![],
+                implicit_deps: vec![],
+                order_only_deps: vec![],
+                validations: vec![],
+            }],
+            vars: BTreeMap::new(),
+            implicit_outputs: vec![],
+            implicit_deps: vec![],
+            order_only_deps: vec![],
+            validations: vec![],
+        };
+        let ast = Generated {
+            name: "gen".to_owned(),
+            rule: ldrule,
+            deps: vec![lib.clone(), lib],
+            vars: BTreeMap::new(),
+            implicit_outputs: vec![],
+            implicit_deps: vec![],
+            order_only_deps: vec![],
+            validations: vec![],
+        };
+
+        let mut writer = Writer::new();
+        writer.add_graph(&DependencyGraph::try_from(ast).unwrap()).unwrap();
+        let mut out = vec![];
+        writer.content_builder.into_doc().render(80, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(3, out.matches("build ").count());
+        for build in ["build foo.o: cc", "build libfoo.a: ar", "build gen: ld"] {
+            assert_eq!(1, out.matches(build).count(), "{}", build);
+        }
+    }
+
+    #[test]
+    fn writer_top_level_declarations() {
+        let ldrule = Rule::new("ld".to_owned(), "gcc $in -o $out".to_owned())
+            .set_pool("link_pool".to_owned());
+
+        let mut writer = Writer::new();
+        writer.add_variable("ninja_required_version", "1.3");
+        writer.add_variable("builddir", "build");
+        writer.add_pool(&Pool::new("link_pool", 2));
+        writer.add_pool(&Pool::new("link_pool", 2));
+        writer.add_rule(&ldrule);
+        writer.add_include("rules.ninja").unwrap();
+        writer.add_subninja("lib/foo/build.ninja").unwrap();
+
+        let mut out = vec![];
+        writer.content_builder.into_doc().render(80, &mut out).unwrap();
+        assert_eq!(
+            "ninja_required_version = 1.3\nbuilddir = build\npool link_pool\n  depth = 2\n\n\
+             rule ld\n  command = gcc $in -o $out\n  pool = link_pool\n\n\
+             include rules.ninja\nsubninja lib/foo/build.ninja\n",
+            String::from_utf8(out).unwrap()
+        );
+    }
+
+    #[test]
+    fn writer_rejects_pipes_in_paths() {
+        let ccrule = Rule::new("cc".to_owned(), "gcc -c $in -o $out".to_owned());
+        let mut writer = Writer::new();
+        writer.add_rule(&ccrule);
+        let mut build = Build::new(ccrule.get_ref(), vec!["a.o".to_owned()], vec!["a.c".to_owned()]);
+        build.implicit_inputs = vec!["gen|erated.h".to_owned()];
+        assert_eq!(
+            "Path 'gen|erated.h' contains '|', which ninja cannot read in a path",
+            writer.add_build(&build).unwrap_err().to_string()
+        );
+        assert!(writer.add_default("a|b".to_owned()).is_err());
+        assert!(writer.add_include("rules|x.ninja").is_err());
+
+        let mut out = vec![];
+        writer.content_builder.into_doc().render(80, &mut out).unwrap();
+        assert_eq!("rule cc\n  command = gcc -c $in -o $out\n\n", String::from_utf8(out).unwrap());
+    }
+
+    #[test]
+    fn writer_graph_dependency_kinds() {
+        let ccrule = Rule::new("cc".to_owned(), "gcc -c $in -o $out".to_owned());
+        let genrule = Rule::new("gen".to_owned(), "gen $out".to_owned());
+        let mut graph = DependencyGraph::default();
+        let source = graph.add_source("a.c");
+        let config = graph.add_source("config.h");
+        let header = graph.add_node(Node::Generated {
+            rule: genrule,
+            outputs: vec!["gen.h".to_owned()],
+            implicit_outputs: vec!["gen.stamp".to_owned()],
+            vars: BTreeMap::new(),
+            validations: vec![],
+        });
+        let object = graph.add_node(Node::Generated {
+            rule: ccrule,
+            outputs: vec!["a.o".to_owned()],
+            implicit_outputs: vec![],
+            vars: BTreeMap::new(),
+            validations: vec!["a.check".to_owned()],
+        });
+        graph.add_dependency(object, source).unwrap();
+        graph
+            .add_dependency_kind(object, config, Edge::Implicit)
+            .unwrap();
+        graph
+            .add_dependency_kind(object, header, Edge::OrderOnly)
+            .unwrap();
+
+        let mut writer = Writer::new();
+        writer.add_graph(&graph).unwrap();
+        let mut out = vec![];
+        writer.content_builder.into_doc().render(80, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("build gen.h | gen.stamp: gen\n"));
+        assert!(out.contains("build a.o: cc a.c | config.h || gen.h |@ a.check\n"));
+    }
+
+    fn object(rule: &Rule, name: &str, flags: &str) -> Node {
+        let mut vars = BTreeMap::new();
+        vars.insert("flags".to_owned(), flags.to_owned());
+        vars.insert("cflags".to_owned(), "-O2".to_owned());
+        Node::Generated {
+            rule: rule.clone(),
+            outputs: vec![name.to_owned()],
+            implicit_outputs: vec![],
+            vars,
+            validations: vec![],
+        }
+    }
+
+    #[test]
+    fn writer_graph_is_deterministic() {
+        let ccrule = Rule::new("cc".to_owned(), "gcc -c $in -o $out".to_owned());
+        let ldrule = Rule::new("ld".to_owned(), "gcc $in -o $out".to_owned());
+        let render = || {
+            let mut graph = DependencyGraph::default();
+            // The executable is added first, its dependencies still need to be written before it
+            let exe = graph.add_node(Node::Generated {
+                rule: ldrule.clone(),
+                outputs: vec!["app".to_owned()],
+                implicit_outputs: vec![],
+                vars: BTreeMap::new(),
+                validations: vec![],
+            });
+            for name in &["main", "util", "args"] {
+                let source = graph.add_source(format!("{}.c", name));
+                let obj = graph.add_node(object(&ccrule, &format!("{}.o", name), "-Wall"));
+                graph.add_dependency(obj, source).unwrap();
+                graph.add_dependency(exe, obj).unwrap();
+            }
+            let mut writer = Writer::new();
+            writer.add_graph(&graph).unwrap();
+            let mut out = vec![];
+            writer.content_builder.into_doc().render(80, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        let first = render();
+        for _ in 0..10 {
+            assert_eq!(first, render());
+        }
+        assert_eq!(
+            "rule cc\n  command = gcc -c $in -o $out\n\n\
+             rule ld\n  command = gcc $in -o $out\n\n\
+             build main.o: cc main.c\n  cflags = -O2\n  flags = -Wall\n\n\
+             build util.o: cc util.c\n  cflags = -O2\n  flags = -Wall\n\n\
+             build args.o: cc args.c\n  cflags = -O2\n  flags = -Wall\n\n\
+             build app: ld main.o util.o args.o\n\n",
+            first
+        );
+    }
 }
@@ -1,14 +1,25 @@
-use ninja::{NinjaAst, Writer};
-use std::fs::File;
-use std::{fmt::Display, path::PathBuf};
+use ninja::{
+    compile_commands, write_compile_commands, DependencyGraph, ExecError, Executor, Pool, Writer,
+};
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use std::process::{exit, ExitStatus, Stdio};
+use std::str::FromStr;
 use structopt::StructOpt;
-use xay::{config::Configuration, clike::{langc, langcpp}, Context};
-use std::process::{ExitStatus, exit};
+use xay::clike::{
+    langc, langcpp, Instrumentation, Sanitizer, Toolchain, COMPILE_RULES, LINK_POOL, TESTS_TARGET,
+};
+use xay::coverage::{clear_counters, collect, gcov_command, print_summary, write_lcov, LCOV_REPORT};
+use xay::install::{uninstall, write_manifest, INSTALL_TARGET, MANIFEST};
+use xay::testing::run_tests;
+use xay::Context;
+use xay::config::Configuration;
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq, StructOpt)]
+#[derive(Clone, Debug, Default, Eq, PartialEq, StructOpt)]
 #[structopt()]
 enum Command {
     #[structopt(about = "Generate the build directory for ninja to build the project")]
+    #[default]
     Generate,
     #[structopt(about = "Generate the build files and build the project")]
     Build,
@@ -16,13 +27,42 @@ enum Command {
         about = "Generate the build files, build the project and run the executable (implies artifact\
         is executable)"
     )]
-    Run,
-}
-
-impl Default for Command {
-    fn default() -> Self {
-        Command::Generate
-    }
+    Run {
+        /// Sets the working directory of the executable [default: current directory]
+        #[structopt(long, parse(from_os_str))]
+        cwd: Option<PathBuf>,
+        /// Sets an environment variable of the executable, as `NAME=VALUE`
+        #[structopt(short, long, number_of_values = 1, parse(try_from_str = parse_env))]
+        env: Vec<(String, String)>,
+        /// Arguments passed to the executable, after `--`
+        #[structopt(last = true)]
+        args: Vec<String>,
+    },
+    #[structopt(
+        about = "Generate the build files and a compile_commands.json compilation database for clangd \
+        and other tooling"
+    )]
+    Compdb,
+    #[structopt(about = "Generate a graphviz graph representation of the dependency graph")]
+    Graph,
+    #[structopt(
+        about = "Generate the build files, build the executables of the tests folder and run \
+        them"
+    )]
+    Test,
+    #[structopt(
+        about = "Generate the build files, build and run the tests with coverage instrumentation, \
+        then report the lines executed (implies --coverage)"
+    )]
+    Coverage,
+    #[structopt(about = "Generate the build files, build the project and install it")]
+    Install {
+        /// Sets the folder to install into
+        #[structopt(long, default_value = "/usr/local", parse(from_os_str))]
+        prefix: PathBuf,
+    },
+    #[structopt(about = "Remove the files installed by the last install of the build folder")]
+    Uninstall,
 }
 
 #[derive(Debug, StructOpt)]
@@ -37,22 +77,81 @@ struct CmdArgs {
     /// Sets the project folder
     #[structopt(default_value = ".", short = "C", parse(from_os_str))]
     path: PathBuf,
-    /// Sets the destination folder, the path to the build artifacts
+    /// Sets the destination folder, the artifacts of each profile being built in a subfolder, itself
+    /// in a subfolder named after the target when cross-compiling. Instrumented builds get their
+    /// own subfolder, such as `debug-address-undefined`.
     #[structopt(default_value = "build", short, long)]
     dest: PathBuf,
+    /// Sets the target triple to cross-compile for, such as `aarch64-linux-gnu`
+    #[structopt(long)]
+    target: Option<String>,
+    /// Sets the path to a toolchain file, replacing the toolchain of the configuration file
+    #[structopt(long, parse(from_os_str))]
+    toolchain: Option<PathBuf>,
+    /// Sets the build profile: `debug`, `release` or a profile of the configuration file
+    #[structopt(long, default_value = "debug")]
+    profile: String,
+    /// Builds with the release profile, shorthand for `--profile release`
+    #[structopt(long, conflicts_with = "profile")]
+    release: bool,
+    /// Builds with the given sanitizers, separated by commas: `address`, `undefined`, `thread` or
+    /// `leak`
+    #[structopt(long, require_delimiter = true)]
+    sanitize: Vec<Sanitizer>,
+    /// Builds with coverage instrumentation, recording the lines executed
+    #[structopt(long)]
+    coverage: bool,
+    /// Prints the detected toolchain and the details of the generation of the build files
+    #[structopt(short, long)]
+    verbose: bool,
     /// Sets the path to the configuration file, for cases where the file name might be non-standard
     /// or that the project has several configuration files
     #[structopt(long, default_value = "xay.yml")]
     config: String,
+    /// Sets the maximum number of concurrent link jobs [default: number of CPUs]
+    #[structopt(long)]
+    link_jobs: Option<usize>,
+    /// Sets the maximum number of concurrent jobs when building [default: number of CPUs]
+    #[structopt(short, long)]
+    jobs: Option<usize>,
+    /// Sets the program running the build: `ninja`, `builtin` or `auto` to use ninja when it is
+    /// installed
+    #[structopt(long, default_value = "auto", possible_values = &["auto", "ninja", "builtin"])]
+    executor: Backend,
+}
+
+/// Program running the build
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Backend {
+    Auto,
+    Ninja,
+    Builtin,
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Self::Auto),
+            "ninja" => Ok(Self::Ninja),
+            "builtin" => Ok(Self::Builtin),
+            _ => Err(format!("Unknown executor {}", s)),
+        }
+    }
 }
 
 fn main() -> anyhow::Result<()> {
     let opt: CmdArgs = CmdArgs::from_args();
 
-    let mut ctx = Context::new(std::env::current_dir()?.join(opt.path));
-    ctx.dest_dir = ctx.src_dir.join(opt.dest);
-    let config_path = ctx.cwd.join(ctx.cwd.join(opt.config));
-    let ninja_path = ctx.dest_dir.join("build.ninja");
+    let profile = if opt.release { "release" } else { &opt.profile };
+    let mut ctx = Context::new(std::env::current_dir()?.join(&opt.path));
+    ctx.verbose = opt.verbose;
+    let config_path = ctx.cwd.join(ctx.cwd.join(&opt.config));
+    if let Some(Command::Install { prefix }) = &opt.cmd {
+        // Ninja runs from the build folder, and the pkg-config files need an absolute prefix
+        ctx.prefix = ctx.cwd.join(prefix).components().collect();
+    }
 
     if !ctx.src_dir.exists() {
         return Err(anyhow::anyhow!(
@@ -61,60 +160,257 @@ fn main() -> anyhow::Result<()> {
         ));
     }
 
-    std::fs::create_dir_all(&ctx.dest_dir).map_err(display_prefix("Create build dir"))?;
-
-    let ast = match serde_yaml::from_reader::<_, Configuration>(
-        File::open(&config_path).map_err(display_prefix("Config file"))?,
-    ) {
-        Ok(Configuration::C { opts }) => langc::handle_project(ctx.clone().map_inner(|_| opts)),
-        Ok(Configuration::CPP { opts }) => langcpp::handle_project(ctx.clone().map_inner(|_| opts)),
-        Err(err) => Err(err.into()),
+    let config = Configuration::load(&config_path)?;
+    let name = config.options().name.clone().unwrap_or_else(|| ctx.name.clone());
+    let is_library = config.options().ty.is_library();
+    let mut toolchain = match &opt.toolchain {
+        Some(path) => Toolchain::load(path)?,
+        None => config.options().toolchain.clone().unwrap_or_default(),
+    };
+    if opt.target.is_some() {
+        toolchain.target = opt.target.clone();
+    }
+    toolchain.set_pkg_config_env()?;
+    let instrumentation = Instrumentation::new(
+        opt.sanitize.clone(),
+        opt.coverage || opt.cmd == Some(Command::Coverage),
+    )?;
+    let dest = ctx.cwd.join(&opt.dest);
+    let dir = format!("{}{}", profile, instrumentation.dir_suffix());
+    ctx.dest_dir = match &toolchain.target {
+        Some(target) => dest.join(target).join(dir),
+        None => dest.join(dir),
+    };
+    let ninja_path = ctx.dest_dir.join("build.ninja");
+    let graph = match config {
+        Configuration::C { opts } => langc::handle_project(
+            ctx.clone().map_inner(|_| opts),
+            profile,
+            &toolchain,
+            &instrumentation,
+        ),
+        Configuration::CPP { opts } => langcpp::handle_project(
+            ctx.clone().map_inner(|_| opts),
+            profile,
+            &toolchain,
+            &instrumentation,
+        ),
     }?;
+    std::fs::create_dir_all(&ctx.dest_dir).map_err(display_prefix("Create build dir"))?;
     let mut writer = Writer::default();
-    writer.add_ast(ast);
+    let link_pool = Pool::new(
+        LINK_POOL,
+        opt.link_jobs
+            .or_else(|| std::thread::available_parallelism().ok().map(|n| n.get()))
+            .unwrap_or(1),
+    );
+    writer.add_pool(&link_pool);
+    writer.add_graph(&graph)?;
     let mut ninja = std::fs::File::create(&ninja_path)
         .map_err(display_prefix(ninja_path.display().to_string()))?;
     writer
         .write_file(&mut ninja, 80)
         .map_err(display_prefix(ninja_path.display().to_string()))?;
 
-    match opt.cmd {
+    match &opt.cmd {
         None | Some(Command::Generate) => {
             println!("Wrote output to {}", ctx.dest_dir.display());
         }
         Some(Command::Build) => {
-            let res = run(
-                "ninja",
-                vec!["-C".to_owned(), ctx.dest_dir.display().to_string()],
-            )
-            .map_err(display_prefix("ninja"))?;
-            exit(res.code().unwrap_or(0));
+            if !build(&opt, &ctx.dest_dir, &graph, link_pool, &[])? {
+                exit(1);
+            }
         }
-        Some(Command::Run) => {
-            let res = run(
-                "ninja",
-                vec!["-C".to_owned(), ctx.dest_dir.display().to_string()],
-            )
-            .map_err(display_prefix("ninja"))?;
-            if let Some(0) = res.code() {
-                let tgt_file = ctx.dest_dir.join(ctx.name);
-                println!();
-                let res = run(tgt_file.clone().clone().to_string_lossy(), vec![])
-                    .map_err(display_prefix(tgt_file.display().to_string()))?;
-                exit(res.code().unwrap_or(0));
-            } else {
-                exit(res.code().unwrap_or(0));
+        Some(Command::Run { cwd, env, args }) => {
+            if is_library {
+                anyhow::bail!("{} is a library project, it cannot be run", name);
             }
+            let executable = graph
+                .defaults()
+                .flat_map(|n| n.explicit_files())
+                .next()
+                .map(|f| ctx.dest_dir.join(f))
+                .ok_or_else(|| anyhow::anyhow!("No executable found in the build graph"))?;
+            if !build(&opt, &ctx.dest_dir, &graph, link_pool, &[])? {
+                exit(1);
+            }
+            println!();
+            let mut command = std::process::Command::new(&executable);
+            command.args(args).envs(env.iter().cloned());
+            if let Some(cwd) = cwd {
+                command.current_dir(cwd);
+            }
+            let res = command
+                .status()
+                .map_err(display_prefix(executable.display().to_string()))?;
+            // Killed by a signal
+            exit(res.code().unwrap_or(1));
+        }
+        Some(Command::Compdb) => {
+            let commands = compile_commands(&graph, &ctx.dest_dir, COMPILE_RULES);
+            write_compile_commands(&commands, &ctx.dest_dir)
+                .map_err(display_prefix("compile_commands.json"))?;
+            println!("Wrote compilation database to {}", ctx.dest_dir.display());
+        }
+        Some(Command::Graph) => {
+            let graph = graph.graph().map(
+                |_, n| n.files().into_iter().collect::<Vec<_>>().join(", "),
+                |_, _| "",
+            );
+            println!("{}", petgraph::dot::Dot::new(&graph));
+        }
+        Some(Command::Test) => {
+            if !test(&opt, &ctx, &graph, link_pool)? {
+                exit(1);
+            }
+        }
+        Some(Command::Coverage) => {
+            // Counts add up across runs, only the ones of this run are reported
+            clear_counters(&ctx.dest_dir).map_err(display_prefix("coverage"))?;
+            let passed = test(&opt, &ctx, &graph, link_pool)?;
+            let root = ctx.cwd.canonicalize()?;
+            let tests_dir = ctx.tests_dir.canonicalize().unwrap_or_else(|_| ctx.tests_dir.clone());
+            let files = collect(&ctx.dest_dir, &gcov_command()?, |p| {
+                p.starts_with(&root) && !p.starts_with(&tests_dir)
+            })
+            .map_err(display_prefix("gcov"))?;
+            println!();
+            print_summary(&files, &root);
+            let report = ctx.dest_dir.join(LCOV_REPORT);
+            write_lcov(&files, &report).map_err(display_prefix(LCOV_REPORT))?;
+            println!("Wrote coverage report to {}", report.display());
+            if !passed {
+                exit(1);
+            }
+        }
+        Some(Command::Install { .. }) => {
+            if !build(&opt, &ctx.dest_dir, &graph, link_pool, &[INSTALL_TARGET])? {
+                exit(1);
+            }
+            let target = graph
+                .node_index(INSTALL_TARGET)
+                .ok_or_else(|| anyhow::anyhow!("No install target in the build graph"))?;
+            let files = graph
+                .dependencies(target)
+                .into_iter()
+                .flat_map(|(_, n)| graph[n].explicit_files())
+                .collect::<Vec<_>>();
+            write_manifest(&ctx.dest_dir, &files).map_err(display_prefix(MANIFEST))?;
+            println!("Installed {} files into {}", files.len(), ctx.prefix.display());
+        }
+        Some(Command::Uninstall) => {
+            let removed = uninstall(&ctx.dest_dir).map_err(display_prefix("uninstall"))?;
+            println!("Removed {} files", removed);
         }
     }
     Ok(())
 }
 
-fn run<S: Into<String>, I: IntoIterator<Item = String>>(cmd: S, args: I) -> std::io::Result<ExitStatus> {
+/// Builds the targets, or the default ones when empty, with ninja or with the built-in executor
+/// when ninja is not available. Returns whether the build succeeded.
+fn build(
+    opt: &CmdArgs,
+    dest_dir: &Path,
+    graph: &DependencyGraph,
+    link_pool: Pool,
+    targets: &[&str],
+) -> anyhow::Result<bool> {
+    if use_ninja(opt.executor) {
+        let mut args = vec!["-C".to_owned(), dest_dir.display().to_string()];
+        args.extend(opt.jobs.map(|j| format!("-j{}", j)));
+        args.extend(targets.iter().map(|t| t.to_string()));
+        let res = run("ninja", args).map_err(display_prefix("ninja"))?;
+        return Ok(res.success());
+    }
+    let mut executor = Executor::new(dest_dir).add_pool(&link_pool);
+    if let Some(jobs) = opt.jobs {
+        executor = executor.set_jobs(jobs);
+    }
+    let result = if targets.is_empty() {
+        executor.build(graph)
+    } else {
+        executor.build_targets(graph, targets)
+    };
+    match result {
+        Ok(0) => {
+            println!("xay: no work to do.");
+            Ok(true)
+        }
+        Ok(_) => Ok(true),
+        // The failed command and its output were already printed
+        Err(ExecError::Failed(_)) => Ok(false),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Builds and runs the executables of the tests folder. Returns whether all tests passed, which
+/// is the case when there are none.
+fn test(
+    opt: &CmdArgs,
+    ctx: &Context<()>,
+    graph: &DependencyGraph,
+    link_pool: Pool,
+) -> anyhow::Result<bool> {
+    let target = match graph.node_index(TESTS_TARGET) {
+        Some(target) => target,
+        None => {
+            println!("No tests found in {}", ctx.tests_dir.display());
+            return Ok(true);
+        }
+    };
+    if !build(opt, &ctx.dest_dir, graph, link_pool, &[TESTS_TARGET])? {
+        return Ok(false);
+    }
+    let tests = graph
+        .dependencies(target)
+        .into_iter()
+        .flat_map(|(_, n)| graph[n].explicit_files())
+        .map(|f| {
+            let path = ctx.dest_dir.join(f);
+            let name = path.file_name().unwrap().to_string_lossy().into_owned();
+            (name, path)
+        })
+        .collect::<Vec<_>>();
+    println!();
+    run_tests(&tests, &ctx.cwd, jobs(opt)).map_err(display_prefix("test"))
+}
+
+/// Maximum number of concurrent jobs, defaulting to the number of CPUs
+fn jobs(opt: &CmdArgs) -> usize {
+    opt.jobs
+        .or_else(|| std::thread::available_parallelism().ok().map(|n| n.get()))
+        .unwrap_or(1)
+}
+
+fn use_ninja(backend: Backend) -> bool {
+    match backend {
+        Backend::Ninja => true,
+        Backend::Builtin => false,
+        Backend::Auto => std::process::Command::new("ninja")
+            .arg("--version")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok(),
+    }
+}
+
+fn run<S: Into<String>, I: IntoIterator<Item = String>>(
+    cmd: S,
+    args: I,
+) -> std::io::Result<ExitStatus> {
     let mut child = std::process::Command::new(cmd.into()).args(args).spawn()?;
     child.wait()
 }
 
-fn display_prefix<S: Into<String>, T: Display>(prefix: S) -> impl (FnOnce(T) -> anyhow::Error) {
+/// Parses a `NAME=VALUE` environment variable
+fn parse_env(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((name, value)) if !name.is_empty() => Ok((name.to_owned(), value.to_owned())),
+        _ => Err(format!("Expected NAME=VALUE, got {}", s)),
+    }
+}
+
+fn display_prefix<S: Into<String>, T: Display>(prefix: S) -> impl FnOnce(T) -> anyhow::Error {
     |value| anyhow::anyhow!("{}: {}", prefix.into(), value)
 }
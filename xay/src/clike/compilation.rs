@@ -1,15 +1,35 @@
-use std::borrow::{BorrowMut, Cow};
+use std::borrow::{Cow, Borrow};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
 use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
 
-use ninja::Rule;
+use ninja::{Deps, Rule};
 
-use crate::utils::find_binary;
+use crate::clike::{CLikeProject, CompilerKind, Language, Sanitizer, Toolchain, Visibility};
+use crate::utils::{find_binary, shell_join};
+
+/// Pool limiting the number of concurrent link jobs, which are the most memory-hungry
+pub const LINK_POOL: &str = "link_pool";
+
+/// Rules compiling a single source file, as listed in the compilation database
+pub const COMPILE_RULES: &[&str] = &["cc", "cxx"];
 
 pub trait Compiler {
     fn add_include_dirs<I: Iterator<Item = impl AsRef<Path>>>(&mut self, dirs: I);
+    fn add_defines<I: Iterator<Item = impl AsRef<str>>>(&mut self, defines: I);
     fn set_opt(&mut self, level: u8);
+    fn set_debug_info(&mut self, debug_info: bool);
     fn set_warning_level(&mut self, level: u8);
-    fn into_rule(self) -> Rule;
+    fn set_werror(&mut self, werror: bool);
+    fn set_lto(&mut self, lto: bool);
+    fn set_sanitizers(&mut self, sanitizers: &[Sanitizer]);
+    fn set_coverage(&mut self, coverage: bool);
+    fn set_gc_sections(&mut self, gc_sections: bool);
+    /// Flags compiling the objects of a project with the given visibility, which is set for each
+    /// project rather than for the whole build
+    fn visibility_flags(&self, visibility: Visibility) -> Vec<String>;
+    fn as_rule(&self) -> Rule;
 }
 
 impl<C: Compiler + Clone> Compiler for Cow<'_, C> {
@@ -17,16 +37,48 @@ impl<C: Compiler + Clone> Compiler for Cow<'_, C> {
         C::add_include_dirs(self.to_mut(), dirs)
     }
 
+    fn add_defines<I: Iterator<Item = impl AsRef<str>>>(&mut self, defines: I) {
+        C::add_defines(self.to_mut(), defines)
+    }
+
     fn set_opt(&mut self, level: u8) {
         C::set_opt(self.to_mut(), level)
     }
 
+    fn set_debug_info(&mut self, debug_info: bool) {
+        C::set_debug_info(self.to_mut(), debug_info)
+    }
+
     fn set_warning_level(&mut self, level: u8) {
         C::set_warning_level(self.to_mut(), level)
     }
 
-    fn into_rule(self) -> Rule {
-        C::into_rule(Cow::into_owned(self))
+    fn set_werror(&mut self, werror: bool) {
+        C::set_werror(self.to_mut(), werror)
+    }
+
+    fn set_lto(&mut self, lto: bool) {
+        C::set_lto(self.to_mut(), lto)
+    }
+
+    fn set_sanitizers(&mut self, sanitizers: &[Sanitizer]) {
+        C::set_sanitizers(self.to_mut(), sanitizers)
+    }
+
+    fn set_coverage(&mut self, coverage: bool) {
+        C::set_coverage(self.to_mut(), coverage)
+    }
+
+    fn set_gc_sections(&mut self, gc_sections: bool) {
+        C::set_gc_sections(self.to_mut(), gc_sections)
+    }
+
+    fn visibility_flags(&self, visibility: Visibility) -> Vec<String> {
+        C::visibility_flags(Cow::borrow(self), visibility)
+    }
+
+    fn as_rule(&self) -> Rule {
+        C::as_rule(Cow::borrow(self))
     }
 }
 
@@ -35,16 +87,48 @@ impl<C: Compiler> Compiler for Box<C> {
         C::add_include_dirs(self.as_mut(), dirs)
     }
 
+    fn add_defines<I: Iterator<Item = impl AsRef<str>>>(&mut self, defines: I) {
+        C::add_defines(self.as_mut(), defines)
+    }
+
     fn set_opt(&mut self, level: u8) {
         C::set_opt(self.as_mut(), level)
     }
 
+    fn set_debug_info(&mut self, debug_info: bool) {
+        C::set_debug_info(self.as_mut(), debug_info)
+    }
+
     fn set_warning_level(&mut self, level: u8) {
         C::set_warning_level(self.as_mut(), level)
     }
 
-    fn into_rule(self) -> Rule {
-        C::into_rule(*self)
+    fn set_werror(&mut self, werror: bool) {
+        C::set_werror(self.as_mut(), werror)
+    }
+
+    fn set_lto(&mut self, lto: bool) {
+        C::set_lto(self.as_mut(), lto)
+    }
+
+    fn set_sanitizers(&mut self, sanitizers: &[Sanitizer]) {
+        C::set_sanitizers(self.as_mut(), sanitizers)
+    }
+
+    fn set_coverage(&mut self, coverage: bool) {
+        C::set_coverage(self.as_mut(), coverage)
+    }
+
+    fn set_gc_sections(&mut self, gc_sections: bool) {
+        C::set_gc_sections(self.as_mut(), gc_sections)
+    }
+
+    fn visibility_flags(&self, visibility: Visibility) -> Vec<String> {
+        C::visibility_flags(self, visibility)
+    }
+
+    fn as_rule(&self) -> Rule {
+        C::as_rule(self)
     }
 }
 
@@ -52,8 +136,13 @@ pub trait Linker {
     fn add_library_dirs<I: Iterator<Item = impl AsRef<Path>>>(&mut self, dirs: I);
     fn set_shared(&mut self, is_shared: bool);
     fn set_position_independent(&mut self, pic: bool);
-    fn into_lib_rule(self) -> Rule;
-    fn into_exe_rule(self) -> Rule;
+    fn set_lto(&mut self, lto: bool);
+    fn set_sanitizers(&mut self, sanitizers: &[Sanitizer]);
+    fn set_coverage(&mut self, coverage: bool);
+    fn set_gc_sections(&mut self, gc_sections: bool);
+    fn set_strip(&mut self, strip: bool);
+    fn as_lib_rule(&self) -> Rule;
+    fn as_exe_rule(&self) -> Rule;
 }
 
 impl<L: Linker + Clone> Linker for Cow<'_, L> {
@@ -69,12 +158,32 @@ impl<L: Linker + Clone> Linker for Cow<'_, L> {
         L::set_position_independent(self.to_mut(), pic)
     }
 
-    fn into_lib_rule(self) -> Rule {
-        L::into_lib_rule(Cow::into_owned(self))
+    fn set_lto(&mut self, lto: bool) {
+        L::set_lto(self.to_mut(), lto)
+    }
+
+    fn set_sanitizers(&mut self, sanitizers: &[Sanitizer]) {
+        L::set_sanitizers(self.to_mut(), sanitizers)
+    }
+
+    fn set_coverage(&mut self, coverage: bool) {
+        L::set_coverage(self.to_mut(), coverage)
+    }
+
+    fn set_gc_sections(&mut self, gc_sections: bool) {
+        L::set_gc_sections(self.to_mut(), gc_sections)
+    }
+
+    fn set_strip(&mut self, strip: bool) {
+        L::set_strip(self.to_mut(), strip)
+    }
+
+    fn as_lib_rule(&self) -> Rule {
+        L::as_lib_rule(self)
     }
 
-    fn into_exe_rule(self) -> Rule {
-        L::into_exe_rule(Cow::into_owned(self))
+    fn as_exe_rule(&self) -> Rule {
+        L::as_exe_rule(self)
     }
 }
 
@@ -91,48 +200,443 @@ impl<L: Linker> Linker for Box<L> {
         L::set_position_independent(self.as_mut(), pic)
     }
 
-    fn into_lib_rule(self) -> Rule {
-        L::into_lib_rule(*self)
+    fn set_lto(&mut self, lto: bool) {
+        L::set_lto(self.as_mut(), lto)
     }
 
-    fn into_exe_rule(self) -> Rule {
-        L::into_exe_rule(*self)
+    fn set_sanitizers(&mut self, sanitizers: &[Sanitizer]) {
+        L::set_sanitizers(self.as_mut(), sanitizers)
     }
+
+    fn set_coverage(&mut self, coverage: bool) {
+        L::set_coverage(self.as_mut(), coverage)
+    }
+
+    fn set_gc_sections(&mut self, gc_sections: bool) {
+        L::set_gc_sections(self.as_mut(), gc_sections)
+    }
+
+    fn set_strip(&mut self, strip: bool) {
+        L::set_strip(self.as_mut(), strip)
+    }
+
+    fn as_exe_rule(&self) -> Rule {
+        L::as_exe_rule(self)
+    }
+
+    fn as_lib_rule(&self) -> Rule {
+        L::as_lib_rule(self)
+    }
+}
+
+/// Tool bundling objects into static libraries
+pub trait Archiver {
+    fn as_rule(&self) -> Rule;
 }
 
+/// `ar` archiver, from GNU binutils or LLVM
 #[derive(Clone, Debug)]
-pub struct GCC {
+pub struct Ar {
     path: PathBuf,
+}
+
+impl Ar {
+    /// Finds the archiver from the `AR` environment variable, either a command name or a path,
+    /// falling back to `ar` then `llvm-ar`
+    pub fn from_env() -> anyhow::Result<Self> {
+        let path = match std::env::var("AR") {
+            Ok(ar) if Path::new(&ar).is_absolute() => PathBuf::from(ar),
+            Ok(ar) => find_binary(&ar)?,
+            Err(_) => find_binary("ar").or_else(|_| find_binary("llvm-ar"))?,
+        };
+        Ok(Self::new(path))
+    }
+
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            path: path.as_ref().to_owned(),
+        }
+    }
+}
+
+impl Archiver for Ar {
+    fn as_rule(&self) -> Rule {
+        // ar adds to existing archives, which would keep objects removed from the project
+        let command = format!("rm -f $out && {} rcs $out @$out.rsp", self.path.display());
+        Rule::new("ar".to_owned(), command)
+            .set_description("Archiving $out".to_owned())
+            .set_rspfile("$out.rsp".to_owned())
+            .set_rspfile_content("$in".to_owned())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct GCC {
+    /// Command running the driver: its program, possibly after a wrapper such as `ccache` and
+    /// followed by options
+    command: Vec<String>,
+    kind: CompilerKind,
+    /// Version of the compiler, when detected
+    version: Option<String>,
+    language: Language,
     inc_dirs: Vec<PathBuf>,
     lib_dirs: Vec<PathBuf>,
     is_shared: bool,
     opt: u8,
+    debug_info: bool,
+    defines: Vec<String>,
     warnings: Vec<String>,
     is_werror: bool,
     is_pic: bool,
+    is_lto: bool,
+    sanitizers: Vec<Sanitizer>,
+    is_coverage: bool,
+    is_gc_sections: bool,
+    is_strip: bool,
+    /// Target triple given to clang
+    target: Option<String>,
+    sysroot: Option<PathBuf>,
 }
 
 impl GCC {
-    pub fn from_env(var: &str) -> Option<Self> {
-        let cmd = std::env::var(var).ok()?;
-        match cmd.as_str() {
-            "gcc" | "clang" => find_binary(&cmd).ok().map(GCC::new),
-            _ => None,
-        }
+    /// Driver from the environment variable, a command such as `gcc-12`, `/opt/llvm/bin/clang` or
+    /// `ccache gcc`, with its family detected. Returns `None` when the variable is unset or empty.
+    pub fn from_env(var: &str) -> anyhow::Result<Option<Self>> {
+        let value = match std::env::var(var) {
+            Ok(value) if !value.trim().is_empty() => value,
+            _ => return Ok(None),
+        };
+        let mut command = shlex::split(&value)
+            .filter(|c| !c.is_empty())
+            .ok_or_else(|| anyhow::anyhow!("{}: invalid command {}", var, value))?;
+        // The build runs from the build folder, possibly with another PATH
+        command[0] = resolve_program(&command[0])
+            .map_err(|err| anyhow::anyhow!("{}: {}", var, err))?
+            .display()
+            .to_string();
+        Ok(Some(Self::from_command(command).detect()))
     }
 
     pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self::from_command(vec![path.as_ref().display().to_string()])
+    }
+
+    fn from_command(command: Vec<String>) -> Self {
         Self {
-            path: path.as_ref().to_owned(),
+            command,
+            kind: CompilerKind::Gcc,
+            version: None,
+            language: Language::C,
             inc_dirs: vec![],
             lib_dirs: vec![],
             is_shared: true,
             opt: 0,
+            debug_info: false,
+            defines: vec![],
             warnings: vec![],
             is_werror: false,
             is_pic: false,
+            is_lto: false,
+            sanitizers: vec![],
+            is_coverage: false,
+            is_gc_sections: false,
+            is_strip: false,
+            target: None,
+            sysroot: None,
         }
     }
+
+    /// Sets the language the driver compiles, or links objects of
+    pub fn set_language(mut self, language: Language) -> Self {
+        self.language = language;
+        self
+    }
+
+    /// Sets the target triple, passed as `--target=` to clang
+    pub fn set_target(mut self, target: Option<String>) -> Self {
+        self.target = target;
+        self
+    }
+
+    /// Sets the root folder of the target system, passed as `--sysroot=`
+    pub fn set_sysroot(mut self, sysroot: Option<PathBuf>) -> Self {
+        self.sysroot = sysroot;
+        self
+    }
+
+    /// Detects the family and version of the compiler from its predefined macros, falling back to
+    /// its `--version` output. Compilers of unknown families are assumed to accept GCC flags.
+    pub fn detect(mut self) -> Self {
+        match probe_macros(&self.command).or_else(|| probe_version(&self.command)) {
+            Some((kind, version)) => {
+                self.kind = kind;
+                self.version = version;
+            }
+            None => eprintln!(
+                "warning: could not detect the compiler family of {}, assuming GCC",
+                shell_join(self.command.iter().cloned())
+            ),
+        }
+        self
+    }
+
+    pub fn kind(&self) -> CompilerKind {
+        self.kind
+    }
+
+    /// Major version of the compiler, when detected
+    fn major_version(&self) -> Option<u32> {
+        self.version.as_deref()?.split('.').next()?.parse().ok()
+    }
+
+    /// Link-time optimisation flag: parallel with GCC 10 and later, ThinLTO with clang
+    fn lto_flag(&self) -> &'static str {
+        match self.kind {
+            CompilerKind::Gcc if self.major_version().is_some_and(|v| v >= 10) => "-flto=auto",
+            CompilerKind::Gcc => "-flto",
+            CompilerKind::Clang => "-flto=thin",
+        }
+    }
+
+    /// Instrumentation flags, given both when compiling and when linking
+    fn instrumentation_flags(&self) -> String {
+        let mut flags = String::new();
+        if !self.sanitizers.is_empty() {
+            let names = self.sanitizers.iter().map(|s| s.name()).collect::<Vec<_>>();
+            flags.push_str(&format!(" -fsanitize={}", names.join(",")));
+        }
+        if self.sanitizers.contains(&Sanitizer::Undefined) {
+            // Undefined behaviour fails tests instead of only being reported
+            flags.push_str(" -fno-sanitize-recover=undefined");
+        }
+        if self.is_coverage {
+            flags.push_str(" --coverage");
+        }
+        flags
+    }
+
+    /// Flags shared by the rules linking libraries and executables
+    fn link_flags(&self) -> String {
+        let mut flags = self.instrumentation_flags();
+        if self.is_gc_sections {
+            flags.push_str(" -Wl,--gc-sections");
+        }
+        if self.is_strip {
+            flags.push_str(" -s");
+        }
+        flags
+    }
+
+    /// Driver program along with the target options, starting every command
+    fn driver(&self) -> String {
+        let mut command = shell_join(self.command.iter().cloned());
+        if let Some(target) = &self.target {
+            command.push_str(&format!(" --target={}", target));
+        }
+        if let Some(sysroot) = &self.sysroot {
+            command.push_str(&format!(" {}", shlex::quote(&format!("--sysroot={}", sysroot.display()))));
+        }
+        command
+    }
+
+    /// Prefix of the rule names, so that each driver gets its own rules
+    fn rule_prefix(&self) -> &'static str {
+        match self.language {
+            Language::C => "",
+            Language::Cpp => "cxx",
+        }
+    }
+}
+
+impl CLikeProject<GCC, GCC, Ar> {
+    /// Finds the toolchain from the `CC`, `CXX`, `LD` and `AR` environment variables, falling back
+    /// to `gcc` and `g++`, then `clang` and `clang++`. The C++ toolchain is optional, projects only
+    /// need it when they have C++ sources.
+    pub fn from_env() -> anyhow::Result<Self> {
+        let cc = match GCC::from_env("CC")? {
+            Some(cc) => cc,
+            None => GCC::new(find_binary("gcc").or_else(|_| find_binary("clang"))?).detect(),
+        };
+        let cxx = match GCC::from_env("CXX")? {
+            Some(cxx) => Some(cxx),
+            None => find_binary("g++")
+                .or_else(|_| find_binary("clang++"))
+                .ok()
+                .map(|cxx| GCC::new(cxx).detect()),
+        };
+        let cxx = cxx.map(|cxx| cxx.set_language(Language::Cpp));
+        let ld = GCC::from_env("LD")?;
+
+        let mut compilers = BTreeMap::new();
+        let mut linkers = BTreeMap::new();
+        linkers.insert(Language::C, ld.clone().unwrap_or_else(|| cc.clone()));
+        compilers.insert(Language::C, cc);
+        if let Some(cxx) = cxx {
+            let cxx_ld = ld.map(|ld| ld.set_language(Language::Cpp));
+            linkers.insert(Language::Cpp, cxx_ld.unwrap_or_else(|| cxx.clone()));
+            compilers.insert(Language::Cpp, cxx);
+        }
+        Ok(Self {
+            compilers,
+            linkers,
+            archiver: Ar::from_env()?,
+        })
+    }
+
+    /// Finds the tools of the toolchain. Host toolchains are found from the environment as in
+    /// [`Self::from_env`], while cross toolchains only use the tools of the target.
+    pub fn from_toolchain(toolchain: &Toolchain) -> anyhow::Result<Self> {
+        let mut project = if toolchain.is_cross() {
+            let (cc, cxx, ar) = match toolchain.compiler {
+                CompilerKind::Gcc => (
+                    toolchain.tool("gcc")?,
+                    toolchain.tool("g++").ok(),
+                    toolchain.tool("ar")?,
+                ),
+                CompilerKind::Clang => (
+                    find_binary("clang")?,
+                    find_binary("clang++").ok(),
+                    find_binary("llvm-ar").or_else(|_| toolchain.tool("ar"))?,
+                ),
+            };
+            let cc = GCC::new(cc).detect();
+            let cxx = cxx.map(|cxx| GCC::new(cxx).detect().set_language(Language::Cpp));
+            let mut compilers = BTreeMap::new();
+            compilers.insert(Language::C, cc);
+            compilers.extend(cxx.map(|cxx| (Language::Cpp, cxx)));
+            Self {
+                linkers: compilers.clone(),
+                compilers,
+                archiver: Ar::new(ar),
+            }
+        } else {
+            Self::from_env()?
+        };
+        for driver in project.compilers.values_mut().chain(project.linkers.values_mut()) {
+            // Clang drivers build for any target, where GCC ones are built for a single one
+            let target = toolchain
+                .target
+                .clone()
+                .filter(|_| driver.kind() == CompilerKind::Clang);
+            *driver = driver
+                .clone()
+                .set_target(target)
+                .set_sysroot(toolchain.sysroot.clone());
+        }
+        Ok(project)
+    }
+
+    /// Prints the tools used to build the project
+    pub fn print_toolchain(&self) {
+        for (language, compiler) in &self.compilers {
+            eprintln!("{} compiler: {}", language.name(), compiler);
+        }
+        for (language, linker) in &self.linkers {
+            eprintln!("{} linker: {}", language.name(), linker);
+        }
+        eprintln!("Archiver: {}", self.archiver.path.display());
+    }
+}
+
+impl fmt::Display for GCC {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.kind {
+            CompilerKind::Gcc => "GCC",
+            CompilerKind::Clang => "Clang",
+        };
+        write!(f, "{} {} ({})", kind, self.version.as_deref().unwrap_or("(unknown version)"), self.driver())
+    }
+}
+
+/// Resolves the program of a command to its path: programs given by path are used as is, others
+/// are searched in PATH
+fn resolve_program(program: &str) -> anyhow::Result<PathBuf> {
+    if !program.contains('/') {
+        return find_binary(program);
+    }
+    let path = std::env::current_dir()?.join(program.trim_start_matches("./"));
+    if !path.is_file() {
+        anyhow::bail!("{} not found", program);
+    }
+    Ok(path)
+}
+
+/// Detects the compiler from the macros it predefines, clang defining the GCC ones as well
+fn probe_macros(command: &[String]) -> Option<(CompilerKind, Option<String>)> {
+    let output = Command::new(&command[0])
+        .args(&command[1..])
+        .args(["-dM", "-E", "-x", "c", "-"])
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()
+        .filter(|o| o.status.success())?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let macros = stdout
+        .lines()
+        .filter_map(|l| {
+            let mut parts = l.strip_prefix("#define ")?.splitn(2, ' ');
+            Some((parts.next()?, parts.next().unwrap_or_default()))
+        })
+        .collect::<HashMap<_, _>>();
+    let version = |names: [&str; 3]| {
+        names
+            .iter()
+            .map(|n| macros.get(n).copied())
+            .collect::<Option<Vec<_>>>()
+            .map(|v| v.join("."))
+    };
+    if macros.contains_key("__clang__") {
+        Some((
+            CompilerKind::Clang,
+            version(["__clang_major__", "__clang_minor__", "__clang_patchlevel__"]),
+        ))
+    } else if macros.contains_key("__GNUC__") {
+        Some((
+            CompilerKind::Gcc,
+            version(["__GNUC__", "__GNUC_MINOR__", "__GNUC_PATCHLEVEL__"]),
+        ))
+    } else {
+        None
+    }
+}
+
+/// Detects the compiler from its `--version` output, for drivers which cannot preprocess from the
+/// standard input
+fn probe_version(command: &[String]) -> Option<(CompilerKind, Option<String>)> {
+    let output = Command::new(&command[0])
+        .args(&command[1..])
+        .arg("--version")
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    parse_version(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Reads the family and version of the compiler from its `--version` output, the version being
+/// the first one outside of the parentheses of the first line, without its distribution suffix
+fn parse_version(output: &str) -> Option<(CompilerKind, Option<String>)> {
+    let first = output.lines().next()?;
+    let kind = if first.contains("clang") {
+        CompilerKind::Clang
+    } else if first.contains("gcc") || first.contains("g++") || output.contains("Free Software Foundation") {
+        CompilerKind::Gcc
+    } else {
+        return None;
+    };
+    let mut depth = 0;
+    let version = first
+        .split_whitespace()
+        .filter(|w| {
+            let outside = depth == 0 && !w.starts_with('(');
+            depth += w.matches('(').count() as i32 - w.matches(')').count() as i32;
+            outside
+        })
+        .find(|w| w.starts_with(|c: char| c.is_ascii_digit()) && w.contains('.'))
+        .and_then(|w| w.split(|c: char| !c.is_ascii_digit() && c != '.').next())
+        .map(|w| w.to_owned());
+    Some((kind, version))
 }
 
 impl Compiler for GCC {
@@ -140,10 +644,18 @@ impl Compiler for GCC {
         self.inc_dirs.extend(dirs.map(|p| p.as_ref().to_owned()));
     }
 
+    fn add_defines<I: Iterator<Item = impl AsRef<str>>>(&mut self, defines: I) {
+        self.defines.extend(defines.map(|d| d.as_ref().to_owned()));
+    }
+
     fn set_opt(&mut self, level: u8) {
         self.opt = level;
     }
 
+    fn set_debug_info(&mut self, debug_info: bool) {
+        self.debug_info = debug_info;
+    }
+
     fn set_warning_level(&mut self, level: u8) {
         let mut w = vec![];
         if level > 1 {
@@ -155,26 +667,80 @@ impl Compiler for GCC {
         self.warnings = w;
     }
 
-    fn into_rule(self) -> Rule {
-        let mut command = self.path.display().to_string();
-        for w in self.warnings {
+    fn set_werror(&mut self, werror: bool) {
+        self.is_werror = werror;
+    }
+
+    fn set_lto(&mut self, lto: bool) {
+        self.is_lto = lto;
+    }
+
+    fn set_sanitizers(&mut self, sanitizers: &[Sanitizer]) {
+        self.sanitizers = sanitizers.to_vec();
+    }
+
+    fn set_coverage(&mut self, coverage: bool) {
+        self.is_coverage = coverage;
+    }
+
+    fn set_gc_sections(&mut self, gc_sections: bool) {
+        self.is_gc_sections = gc_sections;
+    }
+
+    fn visibility_flags(&self, visibility: Visibility) -> Vec<String> {
+        match visibility {
+            Visibility::Default => vec![],
+            // Inline member functions of C++ classes are not exported either
+            Visibility::Hidden => match self.language {
+                Language::C => vec!["-fvisibility=hidden".to_owned()],
+                Language::Cpp => vec![
+                    "-fvisibility=hidden".to_owned(),
+                    "-fvisibility-inlines-hidden".to_owned(),
+                ],
+            },
+        }
+    }
+
+    fn as_rule(&self) -> Rule {
+        let mut command = self.driver();
+        for w in &self.warnings {
             command.push_str(&format!(" -W{}", w));
         }
-        for d in self.inc_dirs {
+        for d in &self.inc_dirs {
             command.push_str(&format!(" -I{}", d.display()));
         }
-        if !self.is_shared {
-            command.push_str(" -static");
-        }
         if self.opt > 0 {
             command.push_str(&format!(" -O{}", self.opt));
         }
+        if self.debug_info {
+            command.push_str(" -g");
+        }
+        if self.is_lto {
+            command.push_str(&format!(" {}", self.lto_flag()));
+        }
+        if self.is_gc_sections {
+            // Each function and variable in its own section, for the linker to drop unused ones
+            command.push_str(" -ffunction-sections -fdata-sections");
+        }
+        command.push_str(&self.instrumentation_flags());
+        if !self.sanitizers.is_empty() {
+            // Readable stack traces in the reports
+            command.push_str(" -fno-omit-frame-pointer");
+        }
         if self.is_werror {
             command.push_str(" -Werror");
         }
-        command.push_str(" -MD -MMD $flags -c -o $out $in");
-        Rule::new("cc".into(), command)
+        for d in &self.defines {
+            command.push_str(&format!(" {}", shlex::quote(&format!("-D{}", d))));
+        }
+        command.push_str(" -MD -MMD -MF $out.d $flags -c -o $out $in");
+        let name = match self.language {
+            Language::C => "cc",
+            Language::Cpp => "cxx",
+        };
+        Rule::new(name.into(), command)
             .set_depfile("$out.d".to_string())
+            .set_deps(Deps::Gcc)
             .set_description("Compiling file $in".to_string())
     }
 }
@@ -192,34 +758,93 @@ impl Linker for GCC {
         self.is_pic = pic;
     }
 
-    fn into_lib_rule(self) -> Rule {
-        let mut command = self.path.display().to_string();
-        for d in self.lib_dirs {
+    fn set_lto(&mut self, lto: bool) {
+        self.is_lto = lto;
+    }
+
+    fn set_sanitizers(&mut self, sanitizers: &[Sanitizer]) {
+        self.sanitizers = sanitizers.to_vec();
+    }
+
+    fn set_coverage(&mut self, coverage: bool) {
+        self.is_coverage = coverage;
+    }
+
+    fn set_gc_sections(&mut self, gc_sections: bool) {
+        self.is_gc_sections = gc_sections;
+    }
+
+    fn set_strip(&mut self, strip: bool) {
+        self.is_strip = strip;
+    }
+
+    fn as_lib_rule(&self) -> Rule {
+        let mut command = self.driver();
+        for d in &self.lib_dirs {
             command.push_str(&format!(" -L{}", d.display()));
         }
-        if self.is_pic && self.is_shared {
+        command.push_str(" -shared");
+        if self.is_pic {
             command.push_str(" -fPIC");
         }
-        if !self.is_shared {
-            command.push_str(" -static");
+        if self.is_lto {
+            command.push_str(&format!(" {}", self.lto_flag()));
         }
-        command.push_str(" $flags -o $out $in");
-        Rule::new("ldlib".to_owned(), command)
+        command.push_str(&self.link_flags());
+        command.push_str(" -o $out @$out.rsp $flags");
+        Rule::new(format!("{}ldlib", self.rule_prefix()), command)
             .set_description("Linking $out".to_owned())
-            .set_depfile("$out.d".to_owned())
+            .set_pool(LINK_POOL.to_owned())
+            .set_rspfile("$out.rsp".to_owned())
+            .set_rspfile_content("$in".to_owned())
     }
 
-    fn into_exe_rule(self) -> Rule {
-        let mut command = self.path.display().to_string();
-        for d in self.lib_dirs {
+    fn as_exe_rule(&self) -> Rule {
+        let mut command = self.driver();
+        for d in &self.lib_dirs {
             command.push_str(&format!(" -L{}", d.display()));
         }
         if !self.is_shared {
             command.push_str(" -static");
         }
-        command.push_str(" $flags -o $out $in");
-        Rule::new("ldexe".to_owned(), command)
+        if self.is_lto {
+            command.push_str(&format!(" {}", self.lto_flag()));
+        }
+        command.push_str(&self.link_flags());
+        command.push_str(" -o $out @$out.rsp $flags");
+        Rule::new(format!("{}ldexe", self.rule_prefix()), command)
             .set_description("Linking $out".to_owned())
-            .set_depfile("$out.d".to_owned())
+            .set_pool(LINK_POOL.to_owned())
+            .set_rspfile("$out.rsp".to_owned())
+            .set_rspfile_content("$in".to_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_version;
+    use crate::clike::CompilerKind;
+
+    #[test]
+    fn compilers_are_detected_from_their_version_output() {
+        let detect = |output: &str| parse_version(output).map(|(kind, v)| (kind, v.unwrap_or_default()));
+        assert_eq!(
+            Some((CompilerKind::Gcc, "12.2.0".to_owned())),
+            detect("gcc (Debian 12.2.0-14+deb12u1) 12.2.0\nCopyright (C) 2022 Free Software Foundation, Inc.\n")
+        );
+        assert_eq!(
+            Some((CompilerKind::Gcc, "13.2.0".to_owned())),
+            detect("x86_64-w64-mingw32-g++ (GCC) 13.2.0\n")
+        );
+        assert_eq!(
+            Some((CompilerKind::Clang, "14.0.0".to_owned())),
+            detect("Ubuntu clang version 14.0.0-1ubuntu1.1\nTarget: x86_64-pc-linux-gnu\n")
+        );
+        assert_eq!(
+            Some((CompilerKind::Clang, "15.0.0".to_owned())),
+            detect("Apple clang version 15.0.0 (clang-1500.3.9.4)\nTarget: arm64-apple-darwin23.4.0\n")
+        );
+        assert_eq!(None, detect("icc (ICC) 2021.10.0 20230609\n"));
+        assert_eq!(None, detect(""));
     }
 }
@@ -1,28 +1,24 @@
-use ninja::NinjaAst;
+use crate::Context;
+use crate::clike::{Ar, BuildOptionsFile, GCC, CLikeProject, BuildOptions, Instrumentation, Toolchain};
+use crate::config::Configuration;
+use ninja::DependencyGraph;
 
-use crate::{
-    clike::{
-        compilation::GCC,
-        opts::{BuildOptions, BuildOptionsFile},
-        CLikeProject,
-    },
-    utils::find_binary,
-    Context,
-};
-
-pub fn handle_project(ctx: Context<BuildOptionsFile>) -> anyhow::Result<NinjaAst> {
+pub fn handle_project(
+    ctx: Context<BuildOptionsFile>,
+    profile: &str,
+    toolchain: &Toolchain,
+    instrumentation: &Instrumentation,
+) -> anyhow::Result<DependencyGraph> {
     let default_name = ctx.name.clone();
-    //let fallback_gcc = || GCC::new(find_binary("gcc").or_else(anyhow::anyhow!("GCC not found")));
-    let fb_cc = find_binary("gcc").map(GCC::new);
-    let fb_ld = find_binary("gcc").map(GCC::new);
-    CLikeProject {
-        compiler: GCC::from_env("cxx")
-            .ok_or(anyhow::anyhow!(""))
-            .or(fb_cc)?,
-        linker: GCC::from_env("ld")
-            .or_else(|| GCC::from_env("cxx"))
-            .ok_or(anyhow::anyhow!(""))
-            .or(fb_ld)?,
+    let profile = ctx.profile(profile)?;
+    let project = CLikeProject::<GCC, GCC, Ar>::from_toolchain(toolchain)?;
+    if ctx.verbose {
+        project.print_toolchain();
     }
-        .gen_ast(ctx.map_inner(|f| BuildOptions::from_file(f, default_name)))
-}
\ No newline at end of file
+    project
+        .set_profile(&profile)
+        .set_instrumentation(instrumentation)
+        .into_graph(ctx.map_inner(|opts| {
+            BuildOptions::from_config(Configuration::CPP { opts }, default_name)
+        }))
+}
@@ -1,122 +1,726 @@
-use std::borrow::{Cow, Borrow};
-use std::ffi::OsStr;
-use std::path::{PathBuf};
+use crate::{
+    clike::{
+        pkgconfig::{PackageFile, PkgConfig},
+    },
+    install::{install_path, install_rule, symlink_rule, INSTALL_TARGET},
+    utils::shell_join,
+    Context,
+};
+use ninja::{DependencyGraph, Node, NodeIndex, Rule};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+use mapset_macros::map;
 
-use ninja::{NinjaAst};
-
-use crate::clike::ast::{Executable, Library, Object,  Source};
-use crate::clike::opts::{BuildOptions};
-use crate::clike::pkgconfig::PkgConfig;
-use compilation::{Compiler, Linker};
-use crate::Context;
-
-pub mod ast;
-pub mod compilation;
+mod compilation;
+pub(crate) mod opts;
+pub mod pkgconfig;
 pub mod langc;
 pub mod langcpp;
-pub mod opts;
-pub mod pkgconfig;
+mod toolchain;
+mod visibility;
 
-#[derive(Clone, Debug)]
-pub struct CLikeProject<C, L> {
-    pub compiler: C,
-    pub linker: L,
-}
-
-impl<C: Compiler + Clone, L: Linker + Clone> CLikeProject<C, L> {
-    pub fn gen_ast(self, ctx: Context<BuildOptions>) -> anyhow::Result<NinjaAst> {
-        let CLikeProject { compiler, linker } = self;
-        let compiler: Cow<C> = Cow::Owned(compiler);
-        let linker: Cow<L> = Cow::Owned(linker);
-        let dependencies: Vec<_> = ctx
-            .system_dependencies
-            .iter()
-            .cloned()
-            .filter_map(PkgConfig::for_library)
-            .collect();
-        let dep_flags: Vec<_> = dependencies
+pub use compilation::*;
+pub use opts::*;
+pub use toolchain::*;
+pub use visibility::*;
+
+pub struct CLikeProject<C, L, A> {
+    /// Compiler of each language
+    pub compilers: BTreeMap<Language, C>,
+    /// Linker driver of each language, the C++ one being used as soon as C++ objects are linked
+    pub linkers: BTreeMap<Language, L>,
+    /// Archiver bundling the objects of static libraries
+    pub archiver: A,
+}
+
+/// Language of a source file
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum Language {
+    C,
+    Cpp,
+}
+
+impl Language {
+    /// Extensions of the source files written in the language
+    pub fn extensions(self) -> &'static [&'static str] {
+        match self {
+            Self::C => &["c"],
+            Self::Cpp => &["cpp", "cc", "cxx", "c++", "C"],
+        }
+    }
+
+    pub fn of<P: AsRef<Path>>(path: P) -> Option<Self> {
+        let ext = path.as_ref().extension()?.to_str()?;
+        [Self::C, Self::Cpp]
             .iter()
-            .flat_map(|p| {
-                let mut v = p.cflags.clone();
-                let flag_lib_name = p
-                    .libname
-                    .to_uppercase()
-                    .chars()
-                    .map(|c| match c {
-                        'A'..='Z' | '0'..='9' => c,
-                        _ => '_',
-                    })
-                    .collect::<String>();
-                v.push(format!("-DHAS_{}", flag_lib_name));
-                v
-            })
-            .collect();
-        let libraries: Vec<_> = dependencies.into_iter().flat_map(|p| p.libs).collect();
-        let sources = walkdir::WalkDir::new(&ctx.src_dir)
+            .copied()
+            .find(|l| l.extensions().contains(&ext))
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::C => "C",
+            Self::Cpp => "C++",
+        }
+    }
+}
+
+/// Phony target building all test executables, which are found in the folder of the same name
+pub const TESTS_TARGET: &str = "tests";
+
+/// Object compiled from a source file of a project
+#[derive(Clone, Copy, Debug)]
+struct ObjectNode {
+    node: NodeIndex,
+    language: Language,
+    /// Whether the source is `main`, which is left out when linking tests
+    is_main: bool,
+}
+
+/// Artifact of a project added to the dependency graph
+#[derive(Clone, Debug)]
+struct ProjectNode {
+    /// Artifacts to link in order to use the project: the project itself, followed by the
+    /// dependencies of static libraries as they are not bundled into the archive
+    link_nodes: Vec<NodeIndex>,
+    /// Public include directories of the project and its dependencies, their `include` folders
+    include_dirs: Vec<PathBuf>,
+    /// Flags needed to link against the project
+    link_flags: Vec<String>,
+    is_shared: bool,
+    /// Builds installing the artifact of the project
+    install_nodes: Vec<NodeIndex>,
+    /// Builds installing the headers and the pkg-config file of a library
+    devel_nodes: Vec<NodeIndex>,
+    /// Whether linking against the project requires the C++ driver, which is the case of static
+    /// libraries containing C++ objects
+    needs_cxx: bool,
+}
+
+impl<C: Compiler, L: Linker + Clone, A: Archiver> CLikeProject<C, L, A> {
+    /// Configures the toolchain for the profile, shared by the project and its dependencies
+    pub fn set_profile(mut self, profile: &Profile) -> Self {
+        for compiler in self.compilers.values_mut() {
+            compiler.set_opt(profile.opt_level);
+            compiler.set_debug_info(profile.debug_info);
+            compiler.set_warning_level(profile.warnings);
+            compiler.set_werror(profile.werror);
+            compiler.add_defines(profile.defines.iter());
+            compiler.set_lto(profile.lto);
+            compiler.set_gc_sections(profile.gc_sections);
+        }
+        for linker in self.linkers.values_mut() {
+            linker.set_lto(profile.lto);
+            linker.set_gc_sections(profile.gc_sections);
+            linker.set_strip(profile.strip);
+        }
+        self
+    }
+
+    pub fn set_instrumentation(mut self, instrumentation: &Instrumentation) -> Self {
+        for compiler in self.compilers.values_mut() {
+            compiler.set_sanitizers(&instrumentation.sanitizers);
+            compiler.set_coverage(instrumentation.coverage);
+        }
+        for linker in self.linkers.values_mut() {
+            linker.set_sanitizers(&instrumentation.sanitizers);
+            linker.set_coverage(instrumentation.coverage);
+        }
+        self
+    }
+
+    pub fn into_graph(self, ctx: Context<BuildOptions>) -> anyhow::Result<DependencyGraph> {
+        if ctx.verbose {
+            eprintln!("Context: {:#?}", ctx);
+        }
+        let mut graph = DependencyGraph::default();
+        let mut missing = vec![];
+        let mut added = HashMap::new();
+        let project = self.add_project(&mut graph, &ctx, &mut added, true, &mut missing)?;
+        if !missing.is_empty() {
+            anyhow::bail!(
+                "Missing required system dependencies:\n  {}",
+                missing.join("\n  ")
+            );
+        }
+        // Static libraries are used along with the archives of their dependencies, which are built
+        // as well
+        for &node in &project.link_nodes {
+            graph.add_default(node);
+        }
+        add_install_target(&mut graph, &ctx, &project, added.values())?;
+        Ok(graph)
+    }
+
+    /// Adds the project to the graph, after recursively adding its source dependencies from the
+    /// `lib` folder. Libraries already added through another dependent are reused from `added`.
+    /// With `with_tests`, the test executables of the `tests` folder are added as well. Required
+    /// system dependencies that could not be found are added to `missing`.
+    fn add_project(
+        &self,
+        graph: &mut DependencyGraph,
+        ctx: &Context<BuildOptions>,
+        added: &mut HashMap<String, ProjectNode>,
+        with_tests: bool,
+        missing: &mut Vec<String>,
+    ) -> anyhow::Result<ProjectNode> {
+        let mut libraries = vec![];
+        let mut links_shared = false;
+        // Requirements of the pkg-config file of a library
+        let mut requires = vec![];
+        for dep in ctx.dependencies()? {
+            let default_name = dep.name.clone();
+            let dep = dep.map_inner(|config| BuildOptions::from_config(config, default_name));
+            if !dep.is_library {
+                anyhow::bail!(
+                    "Dependency {} is not a library project",
+                    dep.cwd.display()
+                );
+            }
+            links_shared |= dep.is_shared;
+            let libname = library_name(&dep.inner.name, dep.is_shared);
+            let lib = match added.get(&libname) {
+                Some(lib) => lib.clone(),
+                None => {
+                    let lib = self.add_project(graph, &dep, added, false, missing)?;
+                    added.insert(libname, lib.clone());
+                    lib
+                }
+            };
+            // Installed static libraries come with all their dependencies, shared ones only with
+            // the shared libraries they use
+            if dep.is_shared || !ctx.is_shared {
+                requires.push(format!("{} = {}", dep.inner.name, dep.version));
+            }
+            libraries.push(lib);
+        }
+
+        // Static artifacts need the private libraries of their system dependencies as well
+        let is_static = !ctx.is_shared;
+        let mut seen = HashSet::new();
+        let mut dependencies = vec![];
+        for dependency in &ctx.system_dependencies {
+            match PkgConfig::for_library(&dependency.name, is_static) {
+                Some(p) if p.satisfies(dependency) => {
+                    if !seen.insert(p.libname.clone()) {
+                        continue;
+                    }
+                    requires.push(dependency.to_string());
+                    dependencies.push(p);
+                }
+                found if dependency.required => {
+                    let found = found.map(|p| format!(", found {}", p.version)).unwrap_or_default();
+                    missing.push(format!("{} (required by {}{})", dependency, ctx.inner.name, found));
+                }
+                _ => {}
+            }
+        }
+        // The `include` folder holds the public headers of the project, also used by its dependents,
+        // while the headers of `src` are private to it
+        let include_dir = ctx.cwd.join("include");
+        // Visibility only matters to shared libraries, static ones have no export header
+        let export_dir = (ctx.is_library && ctx.is_shared).then(|| export_dir(ctx));
+        if let Some(dir) = &export_dir {
+            std::fs::create_dir_all(dir)?;
+            let path = dir.join(ExportHeader::file_name(&ctx.inner.name));
+            ExportHeader::new(&ctx.inner.name)
+                .write(&path)
+                .map_err(|err| anyhow::anyhow!("{}: {}", path.display(), err))?;
+        }
+        let mut include_dirs = Some(include_dir)
+            .filter(|d| d.is_dir())
             .into_iter()
-            .flatten()
-            .map(|f| f.into_path())
-            .filter(|f| f.extension() == Some(OsStr::new("c")))
-            .map(|f| pathdiff::diff_paths(f, &ctx.dest_dir).unwrap())
-            .map(Source);
-        let objects: Vec<_> = sources
-            .map(|s| {
-                let Source(src) = &s;
-                let obj_type = if ctx.is_library { "lib" } else { "exe" };
-                let mut obj = Object::new(
-                    Cow::clone(&compiler),
-                    PathBuf::from(format!("{}.{}", obj_type, &ctx.name))
-                        .join(src.with_extension("o").file_name().unwrap())
-                        .to_string_lossy(),
-                    s,
+            .chain(export_dir)
+            .chain(libraries.iter().flat_map(|l| l.include_dirs.iter().cloned()))
+            .collect::<Vec<_>>();
+        let mut seen = HashSet::new();
+        include_dirs.retain(|d| seen.insert(d.clone()));
+        let mut dep_flags = std::iter::once(&ctx.src_dir)
+            .chain(include_dirs.iter())
+            .map(|d| format!("-I{}", relative_to(d, &ctx.dest_dir).display()))
+            .collect::<Vec<_>>();
+        dep_flags.extend(
+            dependencies
+                .iter()
+                .flat_map(|p| p.cflags.iter().cloned().chain(p.defines())),
+        );
+        // Static libraries may be linked into shared ones as well
+        if ctx.is_library {
+            dep_flags.push("-fPIC".to_owned());
+        }
+        dep_flags.extend(ctx.extra_build_flags.iter().cloned());
+        let flags = |language: Language| {
+            let std = ctx.standard(language).map(|std| format!("-std={}", std));
+            let visibility = self
+                .compilers
+                .get(&language)
+                .map(|c| c.visibility_flags(ctx.visibility))
+                .unwrap_or_default();
+            std.into_iter()
+                .chain(visibility)
+                .chain(dep_flags.iter().cloned())
+                .collect::<Vec<_>>()
+        };
+        let mut ldflags = dependencies
+            .into_iter()
+            .flat_map(|p| p.libs)
+            .collect::<Vec<_>>();
+        ldflags.extend(libraries.iter().flat_map(|l| l.link_flags.iter().cloned()));
+        if links_shared {
+            // Shared libraries are built next to their dependents
+            ldflags.push("-Wl,-rpath,$ORIGIN".to_owned());
+            if !ctx.is_library {
+                // Installed executables find them in the lib folder of the prefix
+                ldflags.push("-Wl,-rpath,$ORIGIN/../lib".to_owned());
+            }
+        }
+        ldflags.extend(ctx.extra_link_flags.iter().cloned());
+        if !ctx.is_library && is_static {
+            let shared = pkgconfig::shared_only_libraries(&ldflags);
+            if !shared.is_empty() {
+                anyhow::bail!(
+                    "Cannot link {} statically, these libraries only provide a shared object:\n  {}",
+                    ctx.inner.name,
+                    shared
+                        .iter()
+                        .map(|(lib, path)| format!("{} ({})", lib, path.display()))
+                        .collect::<Vec<_>>()
+                        .join("\n  ")
                 );
-                obj.flags.extend(dep_flags.iter().cloned());
-                obj.flags
-                    .extend(ctx.extra_build_flags.iter().cloned());
-                obj
+            }
+        }
+
+        let objtype = if ctx.is_library { "lib" } else { "exe" };
+        let objdir = format!("{}.{}", objtype, ctx.inner.name);
+        let objects = self.add_objects(graph, ctx, &ctx.src_dir, &objdir, &flags)?;
+        if objects.is_empty() {
+            anyhow::bail!("No source files were found in {}", ctx.src_dir.display());
+        }
+
+        let needs_cxx = objects.iter().any(|o| o.language == Language::Cpp)
+            || libraries.iter().any(|l| l.needs_cxx);
+        let link_nodes = link_order(libraries.iter().flat_map(|l| l.link_nodes.iter().copied()));
+        let mut linker = self.linker(needs_cxx)?;
+        linker.set_shared(ctx.is_shared);
+        linker.set_position_independent(ctx.is_shared);
+        let node = if ctx.is_library && !ctx.is_shared {
+            // Archives only bundle the objects, the link flags are passed on to dependents
+            graph.add_node(Node::Generated {
+                vars: BTreeMap::new(),
+                outputs: vec![library_name(&ctx.inner.name, false)],
+                implicit_outputs: vec![],
+                validations: vec![],
+                rule: self.archiver.as_rule(),
+            })
+        } else if ctx.is_library {
+            let soname = soname(&ctx.inner.name, &ctx.version);
+            let mut flags = ldflags.clone();
+            flags.push(format!("-Wl,-soname,{}", soname));
+            if ctx.visibility == Visibility::Hidden {
+                // The symbols of the static libraries linked in are not exported either
+                flags.push("-Wl,--exclude-libs,ALL".to_owned());
+            }
+            graph.add_node(Node::Generated {
+                vars: map! { "flags".to_owned() => shell_join(flags.into_iter()) },
+                outputs: vec![library_name(&ctx.inner.name, true)],
+                implicit_outputs: vec![],
+                // Dependents look for the library by its soname when running from the build folder
+                validations: vec![soname],
+                rule: linker.as_lib_rule(),
             })
-            .collect();
-        if objects.len() == 0 {
-            anyhow::bail!("No source files were found");
         } else {
-            let Context {
-                name,
-                dest_dir,
-                inner: BuildOptions {
-                    extra_link_flags,
-                    is_shared,
-                    is_library,
-                    ..
-                },
-                ..
-            } = ctx;
-            if is_library {
-                let mut linker = Cow::clone(&linker);
-                linker.set_shared(is_shared);
-                let exe = Executable {
-                    name,
-                    objects,
-                    libraries: vec![], // TODO: Add source dependencies support
-                    flags: vec![libraries, extra_link_flags].concat(),
-                    linker,
-                };
-
-                Ok(NinjaAst::Default(Box::new(exe.into())))
-            } else {
-                let mut linker = Cow::clone(&linker);
-                linker.set_shared(is_shared);
-
-                let lib = Library {
-                    name,
-                    tgt_dir: dest_dir,
-                    objects,
-                    libraries: vec![],
-                    flags: vec![libraries, extra_link_flags].concat(),
-                    linker,
-                };
-
-                Ok(NinjaAst::Default(Box::new(lib.into())))
+            let exename = ctx.inner.name.clone();
+            graph.add_node(Node::Generated {
+                vars: map! { "flags".to_owned() => shell_join(ldflags.iter().cloned()) },
+                outputs: vec![exename],
+                implicit_outputs: vec![],
+                validations: vec![],
+                rule: linker.as_exe_rule(),
+            })
+        };
+        graph.add_dependencies(node, objects.iter().map(|o| o.node))?;
+        let install_nodes = add_install_artifact(graph, ctx, node)?;
+        let devel_nodes = match ctx.is_library {
+            true => add_install_devel(graph, ctx, &requires)?,
+            false => vec![],
+        };
+
+        if with_tests {
+            // Tests link the objects of the project directly, and can include its private headers
+            let mut test_ldflags = ldflags.clone();
+            if links_shared {
+                test_ldflags.push("-Wl,-rpath,$ORIGIN/..".to_owned());
+            }
+            let mut tests = vec![];
+            for (name, path) in test_sources(&ctx.tests_dir, &ctx.languages)? {
+                let test_objects =
+                    self.add_objects(graph, ctx, &path, &format!("test.{}", name), &flags)?;
+                let needs_cxx =
+                    needs_cxx || test_objects.iter().any(|o| o.language == Language::Cpp);
+                let mut linker = self.linker(needs_cxx)?;
+                // Tests are linked statically along with static executables only. A static
+                // library does not tell how its users link, and the shared libraries it may use
+                // cannot be linked with `-static`.
+                let is_static_executable = !ctx.is_library && !ctx.is_shared;
+                linker.set_shared(!is_static_executable);
+                let test = graph.add_node(Node::Generated {
+                    vars: map! { "flags".to_owned() => shell_join(test_ldflags.iter().cloned()) },
+                    outputs: vec![format!("{}/{}", TESTS_TARGET, name)],
+                    implicit_outputs: vec![],
+                    validations: vec![],
+                    rule: linker.as_exe_rule(),
+                });
+                let project_objects = objects.iter().filter(|o| !o.is_main).map(|o| o.node);
+                graph.add_dependencies(
+                    test,
+                    test_objects
+                        .iter()
+                        .map(|o| o.node)
+                        .chain(project_objects)
+                        .chain(link_nodes.iter().copied()),
+                )?;
+                tests.push(test);
+            }
+            if !tests.is_empty() {
+                let phony = graph.add_node(Node::Generated {
+                    vars: BTreeMap::new(),
+                    outputs: vec![TESTS_TARGET.to_owned()],
+                    implicit_outputs: vec![],
+                    validations: vec![],
+                    rule: Rule::phony(),
+                });
+                graph.add_dependencies(phony, tests)?;
             }
         }
+
+        if ctx.is_library && !ctx.is_shared {
+            Ok(ProjectNode {
+                link_nodes: std::iter::once(node).chain(link_nodes).collect(),
+                include_dirs,
+                link_flags: ldflags,
+                is_shared: false,
+                install_nodes,
+                devel_nodes,
+                needs_cxx,
+            })
+        } else {
+            graph.add_dependencies(node, link_nodes)?;
+            Ok(ProjectNode {
+                link_nodes: vec![node],
+                include_dirs,
+                link_flags: ldflags,
+                is_shared: ctx.is_shared,
+                install_nodes,
+                devel_nodes,
+                needs_cxx: false,
+            })
+        }
+    }
+
+    /// Compiles the sources found in `dir`, which may also be a single file, into objects in the
+    /// `objdir` folder
+    fn add_objects(
+        &self,
+        graph: &mut DependencyGraph,
+        ctx: &Context<BuildOptions>,
+        dir: &Path,
+        objdir: &str,
+        flags: &dyn Fn(Language) -> Vec<String>,
+    ) -> anyhow::Result<Vec<ObjectNode>> {
+        walkdir::WalkDir::new(dir)
+            .sort_by(|a, b| a.file_name().cmp(b.file_name()))
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .map(|f| f.into_path())
+            .filter_map(|f| Some((Language::of(&f).filter(|l| ctx.languages.contains(l))?, f)))
+            .map(|(language, f)| {
+                let is_main = f.file_stem().is_some_and(|stem| stem == "main");
+                let s = relative_to(&f, &ctx.dest_dir).display().to_string();
+                if ctx.verbose {
+                    eprintln!("file: {}", s);
+                }
+                let compiler = self.compilers.get(&language).ok_or_else(|| {
+                    anyhow::anyhow!("No {} compiler found to build {}", language.name(), s)
+                })?;
+                // Objects mirror the folders of the sources, which may share file names
+                let relative = f
+                    .strip_prefix(dir)
+                    .ok()
+                    .filter(|r| !r.as_os_str().is_empty())
+                    .unwrap_or_else(|| Path::new(f.file_name().unwrap()));
+                let path = format!("{}/{}", objdir, relative.with_extension("o").display());
+                let nsource = graph.add_source(s);
+                let node = graph.add_node(Node::Generated {
+                    rule: compiler.as_rule(),
+                    vars: map! {"flags".to_owned() => shell_join(flags(language).into_iter())},
+                    outputs: vec![path],
+                    implicit_outputs: vec![],
+                    validations: vec![],
+                });
+                graph.add_dependency(node, nsource)?;
+                Ok(ObjectNode {
+                    node,
+                    language,
+                    is_main,
+                })
+            })
+            .collect()
+    }
+
+    /// Linker driver for the objects, the C++ one being needed as soon as C++ objects are linked
+    fn linker(&self, needs_cxx: bool) -> anyhow::Result<L> {
+        let language = if needs_cxx { Language::Cpp } else { Language::C };
+        self.linkers
+            .get(&language)
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("No {} linker found", language.name()))
+    }
+}
+
+/// Test executables of the project, one per source file or folder of `dir`, with their names
+fn test_sources(dir: &Path, languages: &[Language]) -> anyhow::Result<Vec<(String, PathBuf)>> {
+    if !dir.is_dir() {
+        return Ok(vec![]);
+    }
+    let mut paths = std::fs::read_dir(dir)?
+        .map(|e| e.map(|e| e.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    paths.sort();
+    Ok(paths
+        .into_iter()
+        .filter(|p| p.is_dir() || Language::of(p).is_some_and(|l| languages.contains(&l)))
+        .filter_map(|p| Some((p.file_stem()?.to_string_lossy().into_owned(), p)))
+        .collect())
+}
+
+/// Adds the builds installing the artifact of the project under the prefix: executables in `bin`,
+/// libraries in `lib`. Shared libraries are installed with their version, and linked from their
+/// soname and from their unversioned name. In the build folder, the soname is linked to the
+/// library as well.
+fn add_install_artifact(
+    graph: &mut DependencyGraph,
+    ctx: &Context<BuildOptions>,
+    node: NodeIndex,
+) -> anyhow::Result<Vec<NodeIndex>> {
+    let name = &ctx.inner.name;
+    if !ctx.is_library {
+        return Ok(vec![add_install(graph, ctx, node, Path::new("bin").join(name), "755")?]);
+    }
+    let libname = library_name(name, ctx.is_shared);
+    if !ctx.is_shared {
+        return Ok(vec![add_install(graph, ctx, node, Path::new("lib").join(libname), "644")?]);
+    }
+
+    let soname = soname(name, &ctx.version);
+    add_symlink(graph, node, soname.clone(), &libname)?;
+    let versioned = format!("{}.{}", libname, ctx.version);
+    let lib = Path::new("lib");
+    let mut nodes = vec![add_install(graph, ctx, node, lib.join(&versioned), "755")?];
+    if versioned != soname {
+        let path = install_path(&ctx.prefix, lib.join(&soname)).display().to_string();
+        nodes.push(add_symlink(graph, nodes[0], path, &versioned)?);
+    }
+    let path = install_path(&ctx.prefix, lib.join(&libname)).display().to_string();
+    nodes.push(add_symlink(graph, nodes[0], path, &soname)?);
+    Ok(nodes)
+}
+
+/// Adds the phony `install` target, installing the project along with the libraries it needs at
+/// runtime: shared libraries, or all of them for static libraries. Libraries also install their
+/// headers and pkg-config file, and the ones of the libraries installed with them.
+fn add_install_target<'a, I: Iterator<Item = &'a ProjectNode>>(
+    graph: &mut DependencyGraph,
+    ctx: &Context<BuildOptions>,
+    project: &ProjectNode,
+    libraries: I,
+) -> anyhow::Result<()> {
+    let libraries = libraries
+        .filter(|l| l.is_shared || (ctx.is_library && !ctx.is_shared))
+        .collect::<Vec<_>>();
+    let mut installs = libraries
+        .iter()
+        .flat_map(|l| l.install_nodes.iter().copied())
+        .collect::<Vec<_>>();
+    if ctx.is_library {
+        installs.extend(libraries.iter().flat_map(|l| l.devel_nodes.iter().copied()));
+    }
+    installs.sort();
+    installs.extend(project.install_nodes.iter().copied());
+    installs.extend(project.devel_nodes.iter().copied());
+
+    let target = graph.add_node(Node::Generated {
+        vars: BTreeMap::new(),
+        outputs: vec![INSTALL_TARGET.to_owned()],
+        implicit_outputs: vec![],
+        validations: vec![],
+        rule: Rule::phony(),
+    });
+    graph.add_dependencies(target, installs)?;
+    Ok(())
+}
+
+/// Writes the pkg-config file of a library, then adds the builds installing it along with the
+/// headers of the `include` folder in `include/<name>`, and the export header of shared libraries
+fn add_install_devel(
+    graph: &mut DependencyGraph,
+    ctx: &Context<BuildOptions>,
+    requires: &[String],
+) -> anyhow::Result<Vec<NodeIndex>> {
+    let name = &ctx.inner.name;
+    let mut installs = vec![];
+    let include_dir = ctx.cwd.join("include");
+    if include_dir.is_dir() {
+        let headers = walkdir::WalkDir::new(&include_dir)
+            .sort_by(|a, b| a.file_name().cmp(b.file_name()))
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()?;
+        for header in headers.into_iter().filter(|e| e.file_type().is_file()) {
+            let path = header.path();
+            let source = graph.add_source(relative_to(path, &ctx.dest_dir).display().to_string());
+            let dest = Path::new("include")
+                .join(name)
+                .join(path.strip_prefix(&include_dir)?);
+            installs.push(add_install(graph, ctx, source, dest, "644")?);
+        }
+    }
+    if ctx.is_shared {
+        let header = ExportHeader::file_name(name);
+        let source = graph.add_source(format!("include.{}/{}", name, header));
+        let dest = Path::new("include").join(name).join(header);
+        installs.push(add_install(graph, ctx, source, dest, "644")?);
+    }
+
+    let pc = format!("{}.pc", name);
+    std::fs::create_dir_all(&ctx.dest_dir)?;
+    let path = ctx.dest_dir.join(&pc);
+    package_file(ctx, requires)
+        .write(&path)
+        .map_err(|err| anyhow::anyhow!("{}: {}", path.display(), err))?;
+    let source = graph.add_source(&pc);
+    installs.push(add_install(graph, ctx, source, Path::new("lib/pkgconfig").join(pc), "644")?);
+    Ok(installs)
+}
+
+/// Folder of the generated export header of a library, one of its public include folders
+fn export_dir(ctx: &Context<BuildOptions>) -> PathBuf {
+    ctx.dest_dir.join(format!("include.{}", ctx.inner.name))
+}
+
+/// Installs the file built by `node` at `path` under the prefix, with the `mode` permissions
+fn add_install<P: AsRef<Path>>(
+    graph: &mut DependencyGraph,
+    ctx: &Context<BuildOptions>,
+    node: NodeIndex,
+    path: P,
+    mode: &str,
+) -> anyhow::Result<NodeIndex> {
+    let install = graph.add_node(Node::Generated {
+        vars: map! { "mode".to_owned() => mode.to_owned() },
+        outputs: vec![install_path(&ctx.prefix, path).display().to_string()],
+        implicit_outputs: vec![],
+        validations: vec![],
+        rule: install_rule(),
+    });
+    graph.add_dependency(install, node)?;
+    Ok(install)
+}
+
+/// Links `path` to `target`, a path relative to its folder, once `node` is built
+fn add_symlink(
+    graph: &mut DependencyGraph,
+    node: NodeIndex,
+    path: String,
+    target: &str,
+) -> anyhow::Result<NodeIndex> {
+    let link = graph.add_node(Node::Generated {
+        vars: map! { "target".to_owned() => target.to_owned() },
+        outputs: vec![path],
+        implicit_outputs: vec![],
+        validations: vec![],
+        rule: symlink_rule(),
+    });
+    graph.add_dependency(link, node)?;
+    Ok(link)
+}
+
+/// pkg-config file of a library project, its headers being installed in `include/<name>`. The
+/// source dependencies installed with it are found through `requires`, as they have their own.
+fn package_file(ctx: &Context<BuildOptions>, requires: &[String]) -> PackageFile {
+    let name = &ctx.inner.name;
+    let mut libs = vec!["-L${libdir}".to_owned(), format!("-l{}", name)];
+    libs.extend(ctx.extra_link_flags.iter().cloned());
+    PackageFile {
+        prefix: ctx.prefix.clone(),
+        name: name.clone(),
+        description: ctx.description.clone().unwrap_or_else(|| name.clone()),
+        version: ctx.version.clone(),
+        requires: requires.to_vec(),
+        cflags: vec![format!("-I${{includedir}}/{}", name)],
+        libs,
+    }
+}
+
+/// Removes duplicate libraries, keeping the last occurrence so that static libraries still come
+/// after the libraries that use them.
+fn link_order<I: IntoIterator<Item = NodeIndex>>(nodes: I) -> Vec<NodeIndex> {
+    let mut nodes = nodes.into_iter().collect::<Vec<_>>();
+    nodes.reverse();
+    let mut seen = HashSet::new();
+    nodes.retain(|n| seen.insert(*n));
+    nodes.reverse();
+    nodes
+}
+
+fn library_name(name: &str, is_shared: bool) -> String {
+    format!("lib{}.{}", name, if is_shared { "so" } else { "a" })
+}
+
+/// Name of the shared library recorded by its dependents, changing with the major version
+fn soname(name: &str, version: &str) -> String {
+    let major = version.split('.').next().unwrap_or(version);
+    format!("{}.{}", library_name(name, true), major)
+}
+
+fn relative_to(path: &Path, base: &Path) -> PathBuf {
+    pathdiff::diff_paths(path, base).unwrap_or_else(|| path.to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{test_sources, Language};
+
+    #[test]
+    fn test_sources_are_named_after_files_and_folders() {
+        let dir = std::env::temp_dir().join(format!("xay-test-sources-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("suite/nested")).unwrap();
+        for file in ["parse.c", "vec.cpp", "notes.txt", "suite/nested/a.c"] {
+            fs::write(dir.join(file), "").unwrap();
+        }
+        let names = |languages: &[Language]| {
+            test_sources(&dir, languages)
+                .unwrap()
+                .into_iter()
+                .map(|(name, path)| (name, path.strip_prefix(&dir).unwrap().to_owned()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            vec![("parse".to_owned(), "parse.c".into()), ("suite".to_owned(), "suite".into())],
+            names(&[Language::C])
+        );
+        assert_eq!(
+            vec!["parse", "suite", "vec"],
+            names(&[Language::C, Language::Cpp])
+                .into_iter()
+                .map(|(name, _)| name)
+                .collect::<Vec<_>>()
+        );
+        assert!(test_sources(&dir.join("missing"), &[Language::C]).unwrap().is_empty());
+        fs::remove_dir_all(dir).unwrap();
     }
 }
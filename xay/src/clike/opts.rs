@@ -1,10 +1,16 @@
-use std::path::PathBuf;
+use std::{collections::BTreeMap, path::PathBuf, str::FromStr};
 
 use serde::Deserialize;
 
-#[derive(Clone, Debug, Deserialize)]
+use crate::{
+    clike::{pkgconfig::SystemDependency, Language, Toolchain, Visibility},
+    config::Configuration,
+};
+
+#[derive(Clone, Debug, Default, Deserialize)]
 pub enum BuildType {
     #[serde(rename="shared executable")]
+    #[default]
     SharedExecutable,
     #[serde(rename="shared library")]
     SharedLibrary,
@@ -14,47 +20,299 @@ pub enum BuildType {
     StaticLibrary,
 }
 
-impl Default for BuildType {
-    fn default() -> Self {
-        Self::SharedExecutable
+impl BuildType {
+    pub fn is_library(&self) -> bool {
+        matches!(self, Self::SharedLibrary | Self::StaticLibrary)
     }
 }
 
 #[derive(Clone, Debug, Deserialize)]
 #[serde(deny_unknown_fields, rename_all = "kebab-case")]
 pub struct BuildOptionsFile {
-    name: Option<String>,
+    pub name: Option<String>,
+    /// Version of the project, written to the pkg-config file of libraries
+    version: Option<String>,
+    /// One-line description of the project, written to the pkg-config file of libraries
+    description: Option<String>,
     #[serde(rename="type", default)]
-    ty: BuildType,
+    pub ty: BuildType,
     #[serde(default)]
-    system_dependencies: Vec<String>,
+    system_dependencies: Vec<SystemDependency>,
     #[serde(default)]
     extra_build_flags: Vec<String>,
     #[serde(default)]
     extra_link_flags: Vec<String>,
+    /// C standard passed to `-std=`, such as `c11`
+    c_standard: Option<String>,
+    /// C++ standard passed to `-std=`, such as `c++17`
+    cxx_standard: Option<String>,
+    /// Build profiles, in addition to or overriding the built-in `debug` and `release` ones
+    #[serde(default)]
+    profiles: BTreeMap<String, ProfileFile>,
+    /// Visibility of the symbols of the project, `hidden` by default for shared libraries and
+    /// `default` otherwise
+    visibility: Option<Visibility>,
+    /// Toolchain of the build, the host one by default. Only the toolchain of the project being
+    /// built is used, dependencies are built with it as well.
+    pub toolchain: Option<Toolchain>,
+}
+
+impl Default for BuildOptionsFile {
+    fn default() -> Self {
+        Self {
+            ty: BuildType::SharedExecutable,
+            name: None,
+            version: None,
+            description: None,
+            system_dependencies: vec![],
+            extra_build_flags: vec![],
+            extra_link_flags: vec![],
+            c_standard: None,
+            cxx_standard: None,
+            profiles: BTreeMap::new(),
+            visibility: None,
+            toolchain: None,
+        }
+    }
+}
+
+impl BuildOptionsFile {
+    /// Resolves the profile of the given name, from the profiles of the file and the built-in ones
+    pub fn profile(&self, name: &str) -> anyhow::Result<Profile> {
+        self.resolve_profile(name, 0)
+    }
+
+    fn resolve_profile(&self, name: &str, depth: usize) -> anyhow::Result<Profile> {
+        if depth > self.profiles.len() {
+            anyhow::bail!("Profile {} inherits from itself", name);
+        }
+        let file = match self.profiles.get(name) {
+            Some(file) => file,
+            None => return Profile::builtin(name),
+        };
+        let mut profile = match &file.inherits {
+            Some(parent) => self.resolve_profile(parent, depth + 1)?,
+            // Overriding a built-in profile only changes the given settings
+            None => Profile::builtin(name).unwrap_or_else(|_| Profile::debug()),
+        };
+        profile.name = name.to_owned();
+        if let Some(level) = file.opt_level {
+            if level > 3 {
+                anyhow::bail!("Profile {}: opt-level must be between 0 and 3", name);
+            }
+            profile.opt_level = level;
+        }
+        if let Some(debug_info) = file.debug_info {
+            profile.debug_info = debug_info;
+        }
+        if let Some(warnings) = file.warnings {
+            profile.warnings = warnings;
+        }
+        if let Some(werror) = file.werror {
+            profile.werror = werror;
+        }
+        profile.defines.extend(file.defines.iter().cloned());
+        if let Some(lto) = file.lto {
+            profile.lto = lto;
+        }
+        if let Some(gc_sections) = file.gc_sections {
+            profile.gc_sections = gc_sections;
+        }
+        if let Some(strip) = file.strip {
+            profile.strip = strip;
+        }
+        Ok(profile)
+    }
+}
+
+/// Build profile as written in the configuration file, where unset settings are inherited
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct ProfileFile {
+    /// Profile to take the unset settings from, defaults to the built-in profile of the same name
+    /// or `debug`
+    pub inherits: Option<String>,
+    pub opt_level: Option<u8>,
+    pub debug_info: Option<bool>,
+    pub warnings: Option<u8>,
+    pub werror: Option<bool>,
+    /// Definitions added to the inherited ones
+    #[serde(default)]
+    pub defines: Vec<String>,
+    pub lto: Option<bool>,
+    pub gc_sections: Option<bool>,
+    pub strip: Option<bool>,
+}
+
+/// Settings applied to the whole build, including dependencies
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Profile {
+    pub name: String,
+    /// Optimisation level, passed to `-O`
+    pub opt_level: u8,
+    /// Whether to emit debug information
+    pub debug_info: bool,
+    /// Warning level, 2 enabling `-Wall` and 3 adding `-Wextra`
+    pub warnings: u8,
+    /// Whether warnings are errors
+    pub werror: bool,
+    /// Preprocessor definitions, as `NAME` or `NAME=VALUE`
+    pub defines: Vec<String>,
+    /// Whether to use link-time optimisation
+    pub lto: bool,
+    /// Whether to remove the functions and data not used, placing each in its own section
+    pub gc_sections: bool,
+    /// Whether to strip the symbol table and debug information of the linked artifacts
+    pub strip: bool,
+}
+
+impl Profile {
+    pub fn debug() -> Self {
+        Self {
+            name: "debug".to_owned(),
+            opt_level: 0,
+            debug_info: true,
+            warnings: 2,
+            werror: false,
+            defines: vec![],
+            lto: false,
+            gc_sections: false,
+            strip: false,
+        }
+    }
+
+    pub fn release() -> Self {
+        Self {
+            name: "release".to_owned(),
+            opt_level: 2,
+            debug_info: false,
+            defines: vec!["NDEBUG".to_owned()],
+            gc_sections: true,
+            strip: true,
+            ..Self::debug()
+        }
+    }
+
+    fn builtin(name: &str) -> anyhow::Result<Self> {
+        match name {
+            "debug" => Ok(Self::debug()),
+            "release" => Ok(Self::release()),
+            _ => anyhow::bail!("Unknown profile {}", name),
+        }
+    }
+}
+
+/// Runtime checker compiled into the build, as given to `-fsanitize=`
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum Sanitizer {
+    Address,
+    Undefined,
+    Thread,
+    Leak,
+}
+
+impl Sanitizer {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Address => "address",
+            Self::Undefined => "undefined",
+            Self::Thread => "thread",
+            Self::Leak => "leak",
+        }
+    }
+}
+
+impl FromStr for Sanitizer {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "address" => Ok(Self::Address),
+            "undefined" => Ok(Self::Undefined),
+            "thread" => Ok(Self::Thread),
+            "leak" => Ok(Self::Leak),
+            _ => Err(format!("Unknown sanitizer {}", s)),
+        }
+    }
+}
+
+/// Instrumentation of a build on top of its profile, applied to the whole build. Instrumented
+/// builds go to their own build folder, see [`Self::dir_suffix`].
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Instrumentation {
+    pub sanitizers: Vec<Sanitizer>,
+    /// Whether to record the lines executed, in gcov files next to the objects
+    pub coverage: bool,
+}
+
+impl Instrumentation {
+    pub fn new(mut sanitizers: Vec<Sanitizer>, coverage: bool) -> anyhow::Result<Self> {
+        sanitizers.sort();
+        sanitizers.dedup();
+        if sanitizers.contains(&Sanitizer::Thread)
+            && sanitizers.iter().any(|s| matches!(s, Sanitizer::Address | Sanitizer::Leak))
+        {
+            anyhow::bail!("The thread sanitizer cannot be combined with the address or leak ones");
+        }
+        Ok(Self {
+            sanitizers,
+            coverage,
+        })
+    }
+
+    /// Suffix of the build folder of the profile, such as `-address-undefined` or `-coverage`, so
+    /// that instrumented objects are never mixed with plain ones
+    pub fn dir_suffix(&self) -> String {
+        let mut suffix = String::new();
+        for sanitizer in &self.sanitizers {
+            suffix.push('-');
+            suffix.push_str(sanitizer.name());
+        }
+        if self.coverage {
+            suffix.push_str("-coverage");
+        }
+        suffix
+    }
 }
 
+/// Version of projects which do not set one
+pub const DEFAULT_VERSION: &str = "0.1.0";
+
 #[derive(Clone, Debug)]
 pub struct BuildOptions {
     pub name: String,
+    pub version: String,
+    pub description: Option<String>,
     pub is_shared: bool,
     pub is_library: bool,
-    pub system_dependencies: Vec<String>,
+    pub system_dependencies: Vec<SystemDependency>,
     pub extra_build_flags: Vec<String>,
     pub extra_link_flags: Vec<String>,
+    /// Languages the sources of the project are written in
+    pub languages: Vec<Language>,
+    pub c_standard: Option<String>,
+    pub cxx_standard: Option<String>,
+    pub visibility: Visibility,
 }
 
 impl BuildOptions {
     pub fn new(name: String) -> Self {
         Self {
             name,
+            version: DEFAULT_VERSION.to_owned(),
+            description: None,
             is_shared: true,
             is_library: false,
             system_dependencies: vec![],
             extra_build_flags: vec![],
             extra_link_flags: vec![],
+            languages: vec![Language::C],
+            c_standard: None,
+            cxx_standard: None,
+            visibility: Visibility::Default,
         }
     }
+
     pub fn from_file(opts: BuildOptionsFile, default_name: String) -> Self {
         let (is_shared, is_library) = match opts.ty {
             BuildType::SharedExecutable => (true, false),
@@ -62,13 +320,42 @@ impl BuildOptions {
             BuildType::StaticExecutable => (false, false),
             BuildType::StaticLibrary => (false, true),
         };
+        // Shared libraries only export the symbols marked with their export macro
+        let visibility = opts.visibility.unwrap_or(match is_library && is_shared {
+            true => Visibility::Hidden,
+            false => Visibility::Default,
+        });
         Self {
             name: opts.name.unwrap_or(default_name),
+            version: opts.version.unwrap_or_else(|| DEFAULT_VERSION.to_owned()),
+            description: opts.description,
             is_shared,
             is_library,
             system_dependencies: opts.system_dependencies,
             extra_build_flags: opts.extra_build_flags,
             extra_link_flags: opts.extra_link_flags,
+            languages: vec![Language::C],
+            c_standard: opts.c_standard,
+            cxx_standard: opts.cxx_standard,
+            visibility,
+        }
+    }
+
+    /// Options of a project, compiling the sources of the languages of its configuration
+    pub fn from_config(config: Configuration, default_name: String) -> Self {
+        let languages = config.languages().to_vec();
+        let (Configuration::C { opts } | Configuration::CPP { opts }) = config;
+        Self {
+            languages,
+            ..Self::from_file(opts, default_name)
+        }
+    }
+
+    /// Language standard to compile the sources of the given language with
+    pub fn standard(&self, language: Language) -> Option<&str> {
+        match language {
+            Language::C => self.c_standard.as_deref(),
+            Language::Cpp => self.cxx_standard.as_deref(),
         }
     }
 }
@@ -79,3 +366,53 @@ pub struct ProcessOptions {
     pub src_dir: PathBuf,
     pub dest_dir: PathBuf,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{BuildOptionsFile, Profile};
+
+    fn options(profiles: &str) -> BuildOptionsFile {
+        serde_yaml::from_str(&format!("profiles:\n{}", profiles)).unwrap()
+    }
+
+    fn error(options: &BuildOptionsFile, name: &str) -> String {
+        options.profile(name).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn profiles_inherit_settings_and_defines() {
+        let options = options(
+            "  a: {inherits: b, lto: true, defines: [A]}\n\
+             \x20 b: {inherits: release, opt-level: 3, defines: [B=1]}\n\
+             \x20 release: {warnings: 3}\n",
+        );
+        let a = options.profile("a").unwrap();
+        assert_eq!("a", a.name);
+        assert_eq!(3, a.opt_level);
+        assert_eq!(3, a.warnings);
+        assert!(a.lto);
+        assert!(!a.debug_info);
+        assert_eq!(vec!["NDEBUG", "B=1", "A"], a.defines);
+
+        // Overriding a built-in profile keeps its other settings
+        let release = options.profile("release").unwrap();
+        assert_eq!(Profile { warnings: 3, ..Profile::release() }, release);
+        assert_eq!(Profile::debug(), options.profile("debug").unwrap());
+    }
+
+    #[test]
+    fn profile_errors() {
+        let options = options(
+            "  self: {inherits: self}\n\
+             \x20 a: {inherits: b}\n\
+             \x20 b: {inherits: a}\n\
+             \x20 orphan: {inherits: missing}\n\
+             \x20 fast: {opt-level: 4}\n",
+        );
+        assert_eq!("Profile self inherits from itself", error(&options, "self"));
+        assert!(error(&options, "a").ends_with("inherits from itself"));
+        assert_eq!("Unknown profile missing", error(&options, "orphan"));
+        assert_eq!("Unknown profile nope", error(&options, "nope"));
+        assert_eq!("Profile fast: opt-level must be between 0 and 3", error(&options, "fast"));
+    }
+}
@@ -1,26 +1,252 @@
-use std::{collections::HashMap, process::Command};
+use std::{
+    collections::{HashMap, HashSet},
+    convert::TryFrom,
+    fmt,
+    path::{Path, PathBuf},
+    process::Command,
+};
+use std::hash::{Hash, Hasher};
+
+use serde::Deserialize;
+
+use crate::utils::write_if_changed;
+
+mod pc;
+
+pub use pc::*;
+
+/// System dependency of a project, found with pkg-config. Written either as the name of the
+/// package, or as a map with a version constraint and whether the dependency is required.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+#[serde(try_from = "SystemDependencyFile")]
+pub struct SystemDependency {
+    pub name: String,
+    pub version: Option<VersionConstraint>,
+    /// Required dependencies fail the build when missing, others only leave `HAS_<NAME>` undefined
+    pub required: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SystemDependencyFile {
+    Name(String),
+    #[serde(rename_all = "kebab-case")]
+    Detailed {
+        name: String,
+        version: Option<String>,
+        #[serde(default)]
+        required: bool,
+    },
+}
+
+impl TryFrom<SystemDependencyFile> for SystemDependency {
+    type Error = String;
+
+    fn try_from(file: SystemDependencyFile) -> Result<Self, Self::Error> {
+        match file {
+            SystemDependencyFile::Name(name) => Ok(Self {
+                name,
+                version: None,
+                required: false,
+            }),
+            SystemDependencyFile::Detailed {
+                name,
+                version,
+                required,
+            } => Ok(Self {
+                version: version.map(VersionConstraint::try_from).transpose()?,
+                name,
+                required,
+            }),
+        }
+    }
+}
+
+impl fmt::Display for SystemDependency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.version {
+            Some(version) => write!(f, "{} {}", self.name, version),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+/// Version constraint on a system dependency, written as `>= 1.16`, `= 1.16` or `<= 1.16`. A bare
+/// version is a minimum version.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum VersionConstraint {
+    AtLeast(String),
+    Exact(String),
+    Max(String),
+}
+
+impl VersionConstraint {
+    pub fn matches(&self, version: &str) -> bool {
+        match self {
+            Self::AtLeast(v) => Comparison::GreaterEqual.matches(version, v),
+            Self::Exact(v) => Comparison::Equal.matches(version, v),
+            Self::Max(v) => Comparison::LessEqual.matches(version, v),
+        }
+    }
+
+    /// Option of the `pkg-config` program checking the constraint, such as `--atleast-version=1.16`
+    fn program_arg(&self) -> String {
+        match self {
+            Self::AtLeast(v) => format!("--atleast-version={}", v),
+            Self::Exact(v) => format!("--exact-version={}", v),
+            Self::Max(v) => format!("--max-version={}", v),
+        }
+    }
+}
+
+impl TryFrom<String> for VersionConstraint {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        let s = s.trim();
+        let (constraint, version): (fn(String) -> Self, _) = if let Some(v) = s.strip_prefix(">=") {
+            (Self::AtLeast, v)
+        } else if let Some(v) = s.strip_prefix("<=") {
+            (Self::Max, v)
+        } else if let Some(v) = s.strip_prefix('=') {
+            (Self::Exact, v)
+        } else {
+            (Self::AtLeast, s)
+        };
+        let version = version.trim();
+        if version.is_empty() || !version.starts_with(|c: char| c.is_ascii_digit()) {
+            return Err(format!(
+                "Invalid version constraint {:?}, expected `>= x.y`, `= x.y` or `<= x.y`",
+                s
+            ));
+        }
+        Ok(constraint(version.to_owned()))
+    }
+}
+
+impl fmt::Display for VersionConstraint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::AtLeast(v) => write!(f, ">= {}", v),
+            Self::Exact(v) => write!(f, "= {}", v),
+            Self::Max(v) => write!(f, "<= {}", v),
+        }
+    }
+}
 
 #[derive(Clone, Debug)]
 pub struct PkgConfig {
     pub libname: String,
+    /// Version of the package found
+    pub version: String,
     pub cflags: Vec<String>,
     pub libs: Vec<String>,
     pub variables: HashMap<String, String>,
+    /// Whether the package was found by the `pkg-config` program rather than from its `.pc` file
+    from_program: bool,
+}
+
+impl PartialEq for PkgConfig {
+    fn eq(&self, other: &Self) -> bool {
+        self.libname.eq(&other.libname)
+    }
+}
+impl Eq for PkgConfig {}
+
+impl Hash for PkgConfig {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.libname.hash(state)
+    }
 }
 
 impl PkgConfig {
-    pub fn for_library<S: AsRef<str>>(name: S) -> Option<Self> {
+    /// Finds the package from its `.pc` file, falling back to the `pkg-config` program when no
+    /// file is found in the search path or it cannot be read. With `is_static`, the libraries are
+    /// the ones needed to link statically, like `pkg-config --static --libs`.
+    pub fn for_library<S: AsRef<str>>(name: S, is_static: bool) -> Option<Self> {
         let name = name.as_ref();
-        if Self::exists(name) {
-            Some(Self {
-                libname: name.to_string(),
-                cflags: shlex::split(&Self::get_value(name, "cflags").unwrap()).unwrap(),
-                libs: shlex::split(&Self::get_value(name, "libs").unwrap()).unwrap(),
-                variables: Self::get_variables(name),
-            })
-        } else {
+        let package = Self::from_pc_files(name, is_static).unwrap_or_else(|err| {
+            eprintln!("warning: {}: {}", name, err);
             None
+        });
+        package.or_else(|| Self::from_program(name, is_static))
+    }
+
+    /// Finds the package of the dependency, if its version satisfies the constraint
+    pub fn for_dependency(dependency: &SystemDependency, is_static: bool) -> Option<Self> {
+        Self::for_library(&dependency.name, is_static).filter(|p| p.satisfies(dependency))
+    }
+
+    /// Whether the version of the package satisfies the constraint of the dependency. Packages
+    /// found by the `pkg-config` program are checked by the program itself.
+    pub fn satisfies(&self, dependency: &SystemDependency) -> bool {
+        match &dependency.version {
+            Some(version) if self.from_program => Self::check_version(&self.libname, version),
+            Some(version) => version.matches(&self.version),
+            None => true,
+        }
+    }
+
+    /// Defines added to the compilation of projects using the package: `HAS_<NAME>`, and
+    /// `<NAME>_VERSION` as a string literal
+    pub fn defines(&self) -> Vec<String> {
+        let name = self
+            .libname
+            .to_uppercase()
+            .chars()
+            .map(|c| match c {
+                'A'..='Z' | '0'..='9' => c,
+                _ => '_',
+            })
+            .collect::<String>();
+        vec![
+            format!("-DHAS_{}", name),
+            format!("-D{}_VERSION=\"{}\"", name, self.version),
+        ]
+    }
+
+    /// Resolves the package and its requirements from the `.pc` files of the search path.
+    /// Returns `None` when the package itself is not found.
+    fn from_pc_files(name: &str, is_static: bool) -> Result<Option<Self>, String> {
+        let resolver = Resolver::from_env(is_static);
+        let pc = match resolver.load(name)? {
+            Some(pc) => pc,
+            None => return Ok(None),
+        };
+        let mut cflags = vec![];
+        let mut libs = vec![];
+        resolver.collect(&pc, true, &mut cflags, &mut libs, &mut vec![name.to_owned()])?;
+
+        // Compile flags keep their first occurrence, libraries their last one so that they still
+        // come after the libraries using them
+        let mut seen = HashSet::new();
+        cflags.retain(|f| seen.insert(f.clone()));
+        seen.clear();
+        libs.reverse();
+        libs.retain(|f| seen.insert(f.clone()));
+        libs.reverse();
+        Ok(Some(Self {
+            libname: name.to_owned(),
+            version: pc.version,
+            cflags: resolver.filter_flags(cflags, "-I", &["/usr/include".to_owned()]),
+            libs: resolver.filter_flags(libs, "-L", &system_lib_dirs()),
+            variables: pc.variables,
+            from_program: false,
+        }))
+    }
+
+    fn from_program(name: &str, is_static: bool) -> Option<Self> {
+        if !Self::exists(name) {
+            return None;
         }
+        Some(Self {
+            libname: name.to_string(),
+            version: Self::get_value(name, &["--modversion"])?,
+            cflags: shlex::split(&Self::get_value(name, &["--cflags"])?)?,
+            libs: shlex::split(&Self::get_value(name, Self::libs_options(is_static))?)?,
+            variables: Self::get_variables(name),
+            from_program: true,
+        })
     }
 
     fn cmd() -> Command {
@@ -41,27 +267,299 @@ impl PkgConfig {
         }
     }
 
-    fn get_value(library: &str, name: &str) -> Option<String> {
-        if let Ok(out) = Self::cmd().arg(format!("--{}", name)).arg(library).output() {
-            String::from_utf8(out.stdout)
-                .map(|s| s.trim().to_string())
+    fn check_version(name: &str, version: &VersionConstraint) -> bool {
+        Self::cmd()
+            .arg(version.program_arg())
+            .arg(name)
+            .status()
+            .is_ok_and(|s| s.success())
+    }
+
+    /// Options of the program printing the libraries to link with
+    fn libs_options(is_static: bool) -> &'static [&'static str] {
+        match is_static {
+            true => &["--static", "--libs"],
+            false => &["--libs"],
+        }
+    }
+
+    fn get_value(library: &str, options: &[&str]) -> Option<String> {
+        let out = Self::cmd().args(options).arg(library).output().ok()?;
+        if !out.status.success() {
+            return None;
+        }
+        String::from_utf8(out.stdout)
+            .map(|s| s.trim().to_string())
+            .ok()
+    }
+
+    fn get_variables(library: &str) -> HashMap<String, String> {
+        Self::get_value(library, &["--print-variables"])
+            .unwrap_or_default()
+            .lines()
+            .filter_map(|var| {
+                let value = Self::get_value(library, &[&format!("--variable={}", var)])?;
+                Some((var.to_owned(), value))
+            })
+            .collect()
+    }
+}
+
+/// `.pc` file of a library project, describing it once installed under `prefix`. The flags may
+/// refer to the `${prefix}`, `${libdir}` and `${includedir}` variables.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PackageFile {
+    pub prefix: PathBuf,
+    pub name: String,
+    pub description: String,
+    pub version: String,
+    /// Packages needed to use the library, written as `name` or `name >= version`
+    pub requires: Vec<String>,
+    pub cflags: Vec<String>,
+    pub libs: Vec<String>,
+}
+
+impl PackageFile {
+    /// Writes the file to `path`, unless it is up to date
+    pub fn write(&self, path: &Path) -> std::io::Result<()> {
+        write_if_changed(path, &self.to_string())
+    }
+}
+
+impl fmt::Display for PackageFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "prefix={}", self.prefix.display())?;
+        writeln!(f, "libdir=${{prefix}}/lib")?;
+        writeln!(f, "includedir=${{prefix}}/include")?;
+        writeln!(f)?;
+        writeln!(f, "Name: {}", self.name)?;
+        writeln!(f, "Description: {}", self.description)?;
+        writeln!(f, "Version: {}", self.version)?;
+        if !self.requires.is_empty() {
+            writeln!(f, "Requires: {}", self.requires.join(", "))?;
+        }
+        writeln!(f, "Cflags: {}", self.cflags.join(" "))?;
+        writeln!(f, "Libs: {}", self.libs.join(" "))
+    }
+}
+
+/// Libraries of the link flags for which only a shared object was found, along with its path.
+/// These cannot be linked into a static executable.
+pub fn shared_only_libraries(ldflags: &[String]) -> Vec<(String, PathBuf)> {
+    let dirs = ldflags
+        .iter()
+        .filter_map(|f| f.strip_prefix("-L"))
+        .map(PathBuf::from)
+        .chain(system_lib_dirs().into_iter().map(PathBuf::from))
+        .collect::<Vec<_>>();
+    let find = |dirs: &[PathBuf], file: &str| {
+        dirs.iter().map(|d| d.join(file)).find(|p| p.is_file())
+    };
+    ldflags
+        .iter()
+        .filter_map(|f| f.strip_prefix("-l"))
+        // `-l:file` names the file to link exactly
+        .filter(|lib| !lib.starts_with(':'))
+        .filter(|lib| find(&dirs, &format!("lib{}.a", lib)).is_none())
+        .filter_map(|lib| Some((lib.to_owned(), find(&dirs, &format!("lib{}.so", lib))?)))
+        .collect()
+}
+
+/// Loads `.pc` files from the search path, following `PKG_CONFIG_SYSROOT_DIR`
+struct Resolver {
+    dirs: Vec<PathBuf>,
+    sysroot: Option<String>,
+    /// Whether private libraries and the libraries of private requirements are needed
+    is_static: bool,
+}
+
+impl Resolver {
+    fn from_env(is_static: bool) -> Self {
+        Self {
+            is_static,
+            dirs: search_path(),
+            sysroot: std::env::var("PKG_CONFIG_SYSROOT_DIR")
                 .ok()
+                .filter(|s| !s.is_empty()),
+        }
+    }
+
+    /// Loads the `.pc` file of the package from the first folder of the search path holding it
+    fn load(&self, name: &str) -> Result<Option<PcFile>, String> {
+        let path = match self
+            .dirs
+            .iter()
+            .map(|d| d.join(format!("{}.pc", name)))
+            .find(|p| p.is_file())
+        {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+        let mut variables = HashMap::new();
+        variables.insert("pc_sysrootdir".to_owned(), self.sysroot.clone().unwrap_or_default());
+        let pc = PcFile::load(&path, variables)?;
+        if pc.version.is_empty() {
+            return Err(format!("{}: missing Version field", path.display()));
+        }
+        Ok(Some(pc))
+    }
+
+    /// Adds the flags of the package and its requirements. Libraries are only added for public
+    /// requirements, while private ones still provide their compile flags, unless linking
+    /// statically. `chain` holds the packages requiring this one, to stop on cycles.
+    fn collect(
+        &self,
+        pc: &PcFile,
+        public: bool,
+        cflags: &mut Vec<String>,
+        libs: &mut Vec<String>,
+        chain: &mut Vec<String>,
+    ) -> Result<(), String> {
+        cflags.extend(pc.cflags.iter().cloned());
+        if public {
+            libs.extend(pc.libs.iter().cloned());
+            if self.is_static {
+                libs.extend(pc.libs_private.iter().cloned());
+            }
+        }
+        let requirements = pc
+            .requires
+            .iter()
+            .map(|r| (r, public))
+            .chain(pc.requires_private.iter().map(|r| (r, public && self.is_static)));
+        for (requirement, public) in requirements {
+            if chain.contains(&requirement.name) {
+                continue;
+            }
+            let dep = self
+                .load(&requirement.name)?
+                .ok_or_else(|| format!("{} requires {}, which was not found", pc.name, requirement))?;
+            if !requirement.matches(&dep.version) {
+                return Err(format!(
+                    "{} requires {}, found version {}",
+                    pc.name, requirement, dep.version
+                ));
+            }
+            chain.push(requirement.name.clone());
+            self.collect(&dep, public, cflags, libs, chain)?;
+            chain.pop();
+        }
+        Ok(())
+    }
+
+    /// Removes the flags adding system folders, which the toolchain searches anyway, and prefixes
+    /// the other folders with the sysroot
+    fn filter_flags(&self, flags: Vec<String>, prefix: &str, system_dirs: &[String]) -> Vec<String> {
+        let allow_system = std::env::var_os(if prefix == "-I" {
+            "PKG_CONFIG_ALLOW_SYSTEM_CFLAGS"
         } else {
-            None
+            "PKG_CONFIG_ALLOW_SYSTEM_LIBS"
+        })
+        .is_some();
+        flags
+            .into_iter()
+            .filter_map(|flag| {
+                let dir = match flag.strip_prefix(prefix) {
+                    Some(dir) => dir,
+                    None => return Some(flag),
+                };
+                if !allow_system && system_dirs.iter().any(|d| d == dir.trim_end_matches('/')) {
+                    return None;
+                }
+                match &self.sysroot {
+                    Some(sysroot) if dir.starts_with('/') && !dir.starts_with(sysroot.as_str()) => {
+                        Some(format!("{}{}{}", prefix, sysroot, dir))
+                    }
+                    _ => Some(flag),
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, convert::TryFrom};
+
+    use super::{PackageFile, PcFile, PkgConfig, Resolver, VersionConstraint};
+
+    #[test]
+    fn pc_files_can_refer_to_the_sysroot() {
+        let dir = std::env::temp_dir().join(format!("xay-pc-sysroot-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("sysx.pc"),
+            "prefix=/usr\n\
+             libdir=${pc_sysrootdir}${prefix}/lib\n\
+             Name: sysx\n\
+             Version: 1.0\n\
+             Libs: -L${libdir} -lsysx\n\
+             Cflags: -I${pcfiledir}/include\n",
+        )
+        .unwrap();
+        let resolver = Resolver {
+            dirs: vec![dir.clone()],
+            sysroot: Some("/opt/sysroot".to_owned()),
+            is_static: false,
+        };
+        let pc = resolver.load("sysx").unwrap().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(vec!["-L/opt/sysroot/usr/lib", "-lsysx"], pc.libs);
+        assert_eq!(vec![format!("-I{}/include", dir.display())], pc.cflags);
+    }
+
+    #[test]
+    fn static_libs_are_printed_with_separate_options() {
+        assert_eq!(&["--static", "--libs"], PkgConfig::libs_options(true));
+        assert_eq!(&["--libs"], PkgConfig::libs_options(false));
+    }
+
+    #[test]
+    fn version_constraints_are_parsed() {
+        let parse = |s: &str| VersionConstraint::try_from(s.to_owned());
+        assert_eq!(Ok(VersionConstraint::AtLeast("1.16".to_owned())), parse(">= 1.16"));
+        assert_eq!(Ok(VersionConstraint::AtLeast("1.16".to_owned())), parse("1.16"));
+        assert_eq!(Ok(VersionConstraint::Exact("2.0".to_owned())), parse("=2.0"));
+        assert_eq!(Ok(VersionConstraint::Max("3".to_owned())), parse(" <= 3 "));
+        for invalid in ["", ">=", "> 1.0", "== 1.0", "latest"] {
+            assert!(parse(invalid).is_err(), "{:?} was accepted", invalid);
         }
     }
 
-    fn get_variables(library: &str) -> HashMap<String, String> {
-        let vars = Self::get_value(library, "print-variables")
-            .map(|s| s.split('\n').map(|s| s.to_owned()).collect())
-            .unwrap_or(vec![]);
-        let mut map = HashMap::new();
-        for var in vars {
-            map.insert(
-                var.clone(),
-                Self::get_value(library, &format!("variable={}", var)).unwrap(),
-            );
-        }
-        map
-    }
-}
\ No newline at end of file
+    #[test]
+    fn version_constraints_compare_versions_like_pkg_config() {
+        let at_least = VersionConstraint::AtLeast("2.9".to_owned());
+        assert!(at_least.matches("2.13.1"));
+        assert!(at_least.matches("2.9"));
+        assert!(!at_least.matches("2.8.10"));
+        assert!(!VersionConstraint::Max("2.9".to_owned()).matches("2.13.1"));
+        assert!(VersionConstraint::Exact("1.2".to_owned()).matches("1.02"));
+        // Like pkg-config, a release candidate is newer than the version it is a suffix of
+        assert!(VersionConstraint::AtLeast("1.0".to_owned()).matches("1.0rc1"));
+        assert!(!VersionConstraint::Max("1.0".to_owned()).matches("1.0rc1"));
+        assert!(VersionConstraint::Max("1.0.1".to_owned()).matches("1.0rc1"));
+        assert_eq!("--max-version=1.0.1", VersionConstraint::Max("1.0.1".to_owned()).program_arg());
+    }
+
+    #[test]
+    fn package_file_is_readable() {
+        let package = PackageFile {
+            prefix: "/opt/foo".into(),
+            name: "foo".to_owned(),
+            description: "Foo library".to_owned(),
+            version: "1.2.0".to_owned(),
+            requires: vec!["zlib".to_owned(), "cairo >= 1.16".to_owned()],
+            cflags: vec!["-I${includedir}/foo".to_owned()],
+            libs: vec!["-L${libdir}".to_owned(), "-lfoo".to_owned()],
+        };
+        let pc = PcFile::parse(&package.to_string(), HashMap::new()).unwrap();
+        assert_eq!("foo", pc.name);
+        assert_eq!("1.2.0", pc.version);
+        assert_eq!(vec!["-I/opt/foo/include/foo"], pc.cflags);
+        assert_eq!(vec!["-L/opt/foo/lib", "-lfoo"], pc.libs);
+        assert_eq!(
+            vec!["zlib", "cairo >= 1.16"],
+            pc.requires.iter().map(|r| r.to_string()).collect::<Vec<_>>()
+        );
+    }
+}
@@ -0,0 +1,411 @@
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+/// Contents of a `.pc` file, with its variables expanded
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct PcFile {
+    pub name: String,
+    pub version: String,
+    pub requires: Vec<Requirement>,
+    /// Packages only needed to compile against the package, or to link it statically
+    pub requires_private: Vec<Requirement>,
+    pub cflags: Vec<String>,
+    pub libs: Vec<String>,
+    /// Libraries only needed to link the package statically
+    pub libs_private: Vec<String>,
+    pub variables: HashMap<String, String>,
+}
+
+impl PcFile {
+    /// Reads the `.pc` file with `variables` predefined, along with `pcfiledir`, its folder
+    pub fn load(path: &Path, mut variables: HashMap<String, String>) -> Result<Self, String> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|err| format!("{}: {}", path.display(), err))?;
+        let dir = path.parent().unwrap_or_else(|| Path::new("."));
+        variables.insert("pcfiledir".to_owned(), dir.display().to_string());
+        Self::parse(&content, variables).map_err(|err| format!("{}: {}", path.display(), err))
+    }
+
+    /// Parses the contents of a `.pc` file, with `variables` predefined
+    pub fn parse(content: &str, variables: HashMap<String, String>) -> Result<Self, String> {
+        let mut file = Self {
+            variables,
+            ..Self::default()
+        };
+        for (number, line) in logical_lines(content).into_iter().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let split = line
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '.'))
+                .filter(|&i| i > 0)
+                .ok_or_else(|| format!("line {}: expected a variable or a field", number + 1))?;
+            let (key, rest) = line.split_at(split);
+            let rest = rest.trim_start();
+            let (separator, value) = rest.split_at(rest.len().min(1));
+            let value = file.expand(value.trim())?;
+            match separator {
+                "=" => {
+                    file.variables.insert(key.to_owned(), value);
+                }
+                ":" => file.set_field(key, &value)?,
+                _ => return Err(format!("line {}: expected `=` or `:` after {}", number + 1, key)),
+            }
+        }
+        Ok(file)
+    }
+
+    fn set_field(&mut self, key: &str, value: &str) -> Result<(), String> {
+        let flags = |value: &str| {
+            shlex::split(value).ok_or_else(|| format!("{}: unbalanced quotes", key))
+        };
+        match key.to_ascii_lowercase().as_str() {
+            "name" => self.name = value.to_owned(),
+            "version" => self.version = value.to_owned(),
+            "requires" => self.requires = parse_requirements(value)?,
+            "requires.private" => self.requires_private = parse_requirements(value)?,
+            "cflags" => self.cflags = flags(value)?,
+            "libs" => self.libs = flags(value)?,
+            "libs.private" => self.libs_private = flags(value)?,
+            // Description, URL, Conflicts and unknown fields are not needed to build
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Expands the `${variable}` references of the value, `$$` being a literal `$`
+    fn expand(&self, value: &str) -> Result<String, String> {
+        let mut out = String::new();
+        let mut rest = value;
+        while let Some(i) = rest.find('$') {
+            out.push_str(&rest[..i]);
+            rest = &rest[i + 1..];
+            if let Some(r) = rest.strip_prefix('$') {
+                out.push('$');
+                rest = r;
+            } else if let Some(r) = rest.strip_prefix('{') {
+                let end = r.find('}').ok_or_else(|| format!("unterminated variable in {}", value))?;
+                let name = &r[..end];
+                let var = self
+                    .variables
+                    .get(name)
+                    .ok_or_else(|| format!("undefined variable {}", name))?;
+                out.push_str(var);
+                rest = &r[end + 1..];
+            } else {
+                out.push('$');
+            }
+        }
+        out.push_str(rest);
+        Ok(out)
+    }
+}
+
+/// Lines of the file without comments, joining lines ending with a backslash
+fn logical_lines(content: &str) -> Vec<String> {
+    let mut lines = vec![];
+    let mut current = String::new();
+    for line in content.lines() {
+        let mut text = String::new();
+        let mut chars = line.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' if chars.peek() == Some(&'#') => {
+                    text.push('#');
+                    chars.next();
+                }
+                '#' => break,
+                _ => text.push(c),
+            }
+        }
+        match text.strip_suffix('\\') {
+            Some(text) => current.push_str(text),
+            None => {
+                current.push_str(&text);
+                lines.push(std::mem::take(&mut current));
+            }
+        }
+    }
+    lines.push(current);
+    lines
+}
+
+/// Comparison operator of a requirement
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Comparison {
+    Less,
+    LessEqual,
+    Equal,
+    NotEqual,
+    GreaterEqual,
+    Greater,
+}
+
+impl Comparison {
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "<" => Some(Self::Less),
+            "<=" => Some(Self::LessEqual),
+            "=" => Some(Self::Equal),
+            "!=" => Some(Self::NotEqual),
+            ">=" => Some(Self::GreaterEqual),
+            ">" => Some(Self::Greater),
+            _ => None,
+        }
+    }
+
+    /// Whether `version` compares to `other` as required
+    pub fn matches(self, version: &str, other: &str) -> bool {
+        let ordering = compare_versions(version, other);
+        match self {
+            Self::Less => ordering == Ordering::Less,
+            Self::LessEqual => ordering != Ordering::Greater,
+            Self::Equal => ordering == Ordering::Equal,
+            Self::NotEqual => ordering != Ordering::Equal,
+            Self::GreaterEqual => ordering != Ordering::Less,
+            Self::Greater => ordering == Ordering::Greater,
+        }
+    }
+
+    fn symbol(self) -> &'static str {
+        match self {
+            Self::Less => "<",
+            Self::LessEqual => "<=",
+            Self::Equal => "=",
+            Self::NotEqual => "!=",
+            Self::GreaterEqual => ">=",
+            Self::Greater => ">",
+        }
+    }
+}
+
+/// Package required by another, with an optional version constraint
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Requirement {
+    pub name: String,
+    pub version: Option<(Comparison, String)>,
+}
+
+impl Requirement {
+    pub fn matches(&self, version: &str) -> bool {
+        match &self.version {
+            Some((comparison, other)) => comparison.matches(version, other),
+            None => true,
+        }
+    }
+}
+
+impl std::fmt::Display for Requirement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.version {
+            Some((comparison, version)) => {
+                write!(f, "{} {} {}", self.name, comparison.symbol(), version)
+            }
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+/// Parses a list of requirements such as `glib-2.0 >= 2.50, gobject-2.0`
+fn parse_requirements(value: &str) -> Result<Vec<Requirement>, String> {
+    let mut tokens = vec![];
+    let mut current = String::new();
+    let mut chars = value.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            ',' | ' ' | '\t' => tokens.push(std::mem::take(&mut current)),
+            '<' | '>' | '=' | '!' => {
+                tokens.push(std::mem::take(&mut current));
+                let mut op = c.to_string();
+                if chars.peek() == Some(&'=') {
+                    op.push('=');
+                    chars.next();
+                }
+                tokens.push(op);
+            }
+            _ => current.push(c),
+        }
+    }
+    tokens.push(current);
+    tokens.retain(|t| !t.is_empty());
+
+    let mut requirements = vec![];
+    let mut tokens = tokens.into_iter().peekable();
+    while let Some(name) = tokens.next() {
+        if Comparison::parse(&name).is_some() {
+            return Err(format!("expected a package name before {} in {}", name, value));
+        }
+        let version = match tokens.peek().and_then(|t| Comparison::parse(t)) {
+            Some(comparison) => {
+                tokens.next();
+                let version = tokens
+                    .next()
+                    .ok_or_else(|| format!("expected a version after {} in {}", name, value))?;
+                Some((comparison, version))
+            }
+            None => None,
+        };
+        requirements.push(Requirement { name, version });
+    }
+    Ok(requirements)
+}
+
+/// Compares versions the way pkg-config does: numeric and alphabetic segments are compared in
+/// turn, numbers numerically, and a number is newer than letters
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+    let (a, b) = (segments(a), segments(b));
+    for (x, y) in a.iter().zip(b.iter()) {
+        let (x_num, y_num) = (is_numeric(x), is_numeric(y));
+        let ordering = match (x_num, y_num) {
+            (true, false) => Ordering::Greater,
+            (false, true) => Ordering::Less,
+            (true, true) => {
+                let (x, y) = (x.trim_start_matches('0'), y.trim_start_matches('0'));
+                x.len().cmp(&y.len()).then_with(|| x.cmp(y))
+            }
+            (false, false) => x.cmp(y),
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    a.len().cmp(&b.len())
+}
+
+fn is_numeric(segment: &str) -> bool {
+    segment.starts_with(|c: char| c.is_ascii_digit())
+}
+
+/// Runs of digits or letters of the version, other characters being separators
+fn segments(version: &str) -> Vec<&str> {
+    let mut segments = vec![];
+    let mut start = None;
+    for (i, c) in version.char_indices() {
+        match start {
+            Some(s) if !c.is_ascii_alphanumeric() => {
+                segments.push(&version[s..i]);
+                start = None;
+            }
+            Some(s) if c.is_ascii_digit() != is_numeric(&version[s..]) => {
+                segments.push(&version[s..i]);
+                start = Some(i);
+            }
+            None if c.is_ascii_alphanumeric() => start = Some(i),
+            _ => {}
+        }
+    }
+    segments.extend(start.map(|s| &version[s..]));
+    segments
+}
+
+/// Folders searched for `.pc` files: the ones of `PKG_CONFIG_PATH`, followed by the ones of
+/// `PKG_CONFIG_LIBDIR` or the default folders when unset
+pub fn search_path() -> Vec<PathBuf> {
+    let split = |var: &str| {
+        std::env::var_os(var).map(|paths| {
+            std::env::split_paths(&paths)
+                .filter(|p| !p.as_os_str().is_empty())
+                .collect::<Vec<_>>()
+        })
+    };
+    let mut dirs = split("PKG_CONFIG_PATH").unwrap_or_default();
+    dirs.extend(split("PKG_CONFIG_LIBDIR").unwrap_or_else(default_search_path));
+    dirs
+}
+
+fn default_search_path() -> Vec<PathBuf> {
+    let multiarch = format!("{}-linux-gnu", std::env::consts::ARCH);
+    vec![
+        format!("/usr/local/lib/{}/pkgconfig", multiarch),
+        "/usr/local/lib/pkgconfig".to_owned(),
+        "/usr/local/share/pkgconfig".to_owned(),
+        format!("/usr/lib/{}/pkgconfig", multiarch),
+        "/usr/lib/pkgconfig".to_owned(),
+        "/usr/lib64/pkgconfig".to_owned(),
+        "/usr/share/pkgconfig".to_owned(),
+    ]
+    .into_iter()
+    .map(PathBuf::from)
+    .collect()
+}
+
+/// Library folders searched by the linker by default, which pkg-config leaves out of `-L` flags
+pub fn system_lib_dirs() -> Vec<String> {
+    let multiarch = format!("{}-linux-gnu", std::env::consts::ARCH);
+    vec![
+        "/usr/lib".to_owned(),
+        "/lib".to_owned(),
+        "/usr/lib64".to_owned(),
+        "/lib64".to_owned(),
+        format!("/usr/lib/{}", multiarch),
+        format!("/lib/{}", multiarch),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cmp::Ordering, collections::HashMap};
+
+    use super::{compare_versions, Comparison, PcFile, Requirement};
+
+    #[test]
+    fn pc_file_expands_variables() {
+        let pc = PcFile::parse(
+            "prefix=/opt/foo # install prefix\n\
+             libdir=${prefix}/lib\n\
+             price=$$5\n\
+             \n\
+             Name: Foo\n\
+             Version: 1.2.3\n\
+             Requires: bar >= 2.0, baz\n\
+             Requires.private: qux<3\n\
+             Libs: -L${libdir} \\\n  -lfoo\n\
+             Libs.private: -lm\n\
+             Cflags: -I\"${prefix}/include/foo bar\" -DFOO\n",
+            HashMap::new(),
+        )
+        .unwrap();
+        assert_eq!("Foo", pc.name);
+        assert_eq!("1.2.3", pc.version);
+        assert_eq!("$5", pc.variables["price"]);
+        assert_eq!(vec!["-L/opt/foo/lib", "-lfoo"], pc.libs);
+        assert_eq!(vec!["-lm"], pc.libs_private);
+        assert_eq!(vec!["-I/opt/foo/include/foo bar", "-DFOO"], pc.cflags);
+        assert_eq!(
+            vec![
+                Requirement {
+                    name: "bar".to_owned(),
+                    version: Some((Comparison::GreaterEqual, "2.0".to_owned())),
+                },
+                Requirement {
+                    name: "baz".to_owned(),
+                    version: None,
+                },
+            ],
+            pc.requires
+        );
+        assert_eq!("qux < 3", pc.requires_private[0].to_string());
+    }
+
+    #[test]
+    fn pc_file_errors() {
+        for content in &["Libs: ${missing}", "Cflags: \"-I/a", "Requires: >= 2", "Requires: a >="] {
+            assert!(PcFile::parse(content, HashMap::new()).is_err(), "{}", content);
+        }
+    }
+
+    #[test]
+    fn versions_compare_by_segment() {
+        assert_eq!(Ordering::Less, compare_versions("1.9", "1.10"));
+        assert_eq!(Ordering::Equal, compare_versions("1.01", "1.1"));
+        assert_eq!(Ordering::Greater, compare_versions("1.2.1", "1.2"));
+        assert_eq!(Ordering::Less, compare_versions("1.2", "1.2rc1"));
+        assert_eq!(Ordering::Greater, compare_versions("2.0a", "1.9z"));
+        assert!(Comparison::GreaterEqual.matches("1.16.0", "1.16"));
+        assert!(!Comparison::Less.matches("3.0", "3"));
+    }
+}
@@ -0,0 +1,138 @@
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+
+use crate::utils::find_binary;
+
+/// Family of the compiler drivers, which changes the names of the tools of a cross toolchain
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum CompilerKind {
+    /// `<prefix>gcc`, `<prefix>g++` and `<prefix>ar`
+    #[default]
+    Gcc,
+    /// `clang` and `clang++` given `--target=<triple>`, and `llvm-ar` or `<prefix>ar`
+    Clang,
+}
+
+/// Toolchain building the project, either for the host or cross-compiling for another target. Set
+/// in the `toolchain` section of the configuration file or in a separate toolchain file.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Toolchain {
+    /// Target triple, such as `aarch64-linux-gnu`, unset when building for the host
+    pub target: Option<String>,
+    #[serde(default)]
+    pub compiler: CompilerKind,
+    /// Prefix of the names of the tools, the target followed by `-` by default. It may also be a
+    /// path, such as `/opt/arm/bin/arm-none-eabi-`.
+    pub prefix: Option<String>,
+    /// Root folder of the target system, passed to `--sysroot`
+    pub sysroot: Option<PathBuf>,
+    /// Folders holding the `.pc` files of the target, the pkg-config folders of the sysroot, or
+    /// the multiarch ones of the target without sysroot, by default
+    #[serde(default)]
+    pub pkg_config_libdir: Vec<PathBuf>,
+}
+
+impl Toolchain {
+    /// Reads a toolchain file
+    pub fn load<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path).map_err(|err| anyhow::anyhow!("{}: {}", path.display(), err))?;
+        serde_yaml::from_reader(file).map_err(|err| anyhow::anyhow!("{}: {}", path.display(), err))
+    }
+
+    /// Whether the toolchain builds for another target than the host
+    pub fn is_cross(&self) -> bool {
+        self.target.is_some() || self.prefix.is_some()
+    }
+
+    /// Prefix of the names of the tools
+    pub fn prefix(&self) -> String {
+        match (&self.prefix, &self.target) {
+            (Some(prefix), _) => prefix.clone(),
+            (None, Some(target)) => format!("{}-", target),
+            (None, None) => String::new(),
+        }
+    }
+
+    /// Finds the tool of the toolchain, which is used as is when the prefix is a path
+    pub fn tool(&self, name: &str) -> anyhow::Result<PathBuf> {
+        let tool = format!("{}{}", self.prefix(), name);
+        if Path::new(&tool).is_absolute() {
+            if !Path::new(&tool).is_file() {
+                anyhow::bail!("Tool {} not found", tool);
+            }
+            Ok(PathBuf::from(tool))
+        } else {
+            find_binary(&tool)
+        }
+    }
+
+    /// Folders holding the `.pc` files of the target, none meaning the ones of the host
+    pub fn pkg_config_dirs(&self) -> Vec<PathBuf> {
+        if !self.pkg_config_libdir.is_empty() {
+            return self.pkg_config_libdir.clone();
+        }
+        if !self.is_cross() && self.sysroot.is_none() {
+            return vec![];
+        }
+        let root = self.sysroot.clone().unwrap_or_else(|| PathBuf::from("/"));
+        let mut dirs = vec![];
+        if let Some(target) = &self.target {
+            dirs.push(root.join("usr/lib").join(target).join("pkgconfig"));
+        }
+        if self.sysroot.is_some() {
+            dirs.push(root.join("usr/lib/pkgconfig"));
+        }
+        dirs.push(root.join("usr/share/pkgconfig"));
+        dirs
+    }
+
+    /// Points pkg-config at the packages of the target, through the `PKG_CONFIG_SYSROOT_DIR` and
+    /// `PKG_CONFIG_LIBDIR` environment variables. Variables already set are left untouched.
+    pub fn set_pkg_config_env(&self) -> anyhow::Result<()> {
+        if let Some(sysroot) = &self.sysroot {
+            if std::env::var_os("PKG_CONFIG_SYSROOT_DIR").is_none() {
+                std::env::set_var("PKG_CONFIG_SYSROOT_DIR", sysroot);
+            }
+        }
+        let dirs = self.pkg_config_dirs();
+        if !dirs.is_empty() && std::env::var_os("PKG_CONFIG_LIBDIR").is_none() {
+            std::env::set_var("PKG_CONFIG_LIBDIR", std::env::join_paths(dirs)?);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::{CompilerKind, Toolchain};
+
+    #[test]
+    fn toolchain_defaults_follow_target() {
+        let toolchain: Toolchain = serde_yaml::from_str(
+            "target: aarch64-linux-gnu\n\
+             sysroot: /opt/sysroot\n",
+        )
+        .unwrap();
+        assert_eq!(CompilerKind::Gcc, toolchain.compiler);
+        assert_eq!("aarch64-linux-gnu-", toolchain.prefix());
+        assert_eq!(
+            vec![
+                PathBuf::from("/opt/sysroot/usr/lib/aarch64-linux-gnu/pkgconfig"),
+                PathBuf::from("/opt/sysroot/usr/lib/pkgconfig"),
+                PathBuf::from("/opt/sysroot/usr/share/pkgconfig"),
+            ],
+            toolchain.pkg_config_dirs()
+        );
+        assert!(Toolchain::default().pkg_config_dirs().is_empty());
+        assert!(!Toolchain::default().is_cross());
+    }
+}
@@ -0,0 +1,80 @@
+use std::{fmt, path::Path};
+
+use serde::Deserialize;
+
+use crate::utils::write_if_changed;
+
+/// Visibility of the symbols of a project, outside of the shared library or executable it is
+/// linked into
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Visibility {
+    /// Every symbol which is not `static` is exported
+    #[default]
+    Default,
+    /// Only the symbols marked with the export macro of the project are exported
+    Hidden,
+}
+
+/// Header defining the export macro of a library, `<NAME>_EXPORT`, which marks the symbols it
+/// exports when built with hidden visibility
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ExportHeader {
+    /// Prefix of the macros, the name of the library in uppercase
+    pub prefix: String,
+}
+
+impl ExportHeader {
+    pub fn new(name: &str) -> Self {
+        let prefix = name
+            .chars()
+            .map(|c| match c.is_ascii_alphanumeric() {
+                true => c.to_ascii_uppercase(),
+                false => '_',
+            })
+            .collect();
+        Self { prefix }
+    }
+
+    /// Name of the header of the library, included as `#include "<name>_export.h"`
+    pub fn file_name(name: &str) -> String {
+        format!("{}_export.h", name)
+    }
+
+    /// Writes the header to `path`, unless it is up to date
+    pub fn write(&self, path: &Path) -> std::io::Result<()> {
+        write_if_changed(path, &self.to_string())
+    }
+}
+
+impl fmt::Display for ExportHeader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let prefix = &self.prefix;
+        writeln!(f, "/* Generated by xay, do not edit */")?;
+        writeln!(f, "#ifndef {}_EXPORT_H", prefix)?;
+        writeln!(f, "#define {}_EXPORT_H", prefix)?;
+        writeln!(f)?;
+        writeln!(f, "#if defined(__GNUC__)")?;
+        writeln!(f, "#define {}_EXPORT __attribute__((visibility(\"default\")))", prefix)?;
+        writeln!(f, "#define {}_NO_EXPORT __attribute__((visibility(\"hidden\")))", prefix)?;
+        writeln!(f, "#else")?;
+        writeln!(f, "#define {}_EXPORT", prefix)?;
+        writeln!(f, "#define {}_NO_EXPORT", prefix)?;
+        writeln!(f, "#endif")?;
+        writeln!(f)?;
+        writeln!(f, "#endif")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ExportHeader;
+
+    #[test]
+    fn export_macros_are_named_after_library() {
+        let header = ExportHeader::new("my-lib2").to_string();
+        assert_eq!("my-lib2_export.h", ExportHeader::file_name("my-lib2"));
+        assert!(header.contains("#define MY_LIB2_EXPORT __attribute__((visibility(\"default\")))\n"));
+        assert!(header.contains("#ifndef MY_LIB2_EXPORT_H\n"));
+    }
+}
@@ -1,5 +1,6 @@
-use crate::clike::opts::BuildOptionsFile;
+use crate::clike::{opts::BuildOptionsFile, Language};
 use serde::Deserialize;
+use std::{fs::File, path::Path};
 
 #[derive(Clone, Debug, Deserialize)]
 #[serde(tag = "language")]
@@ -15,3 +16,28 @@ pub enum Configuration {
         opts: BuildOptionsFile,
     }
 }
+
+impl Configuration {
+    /// Languages of the sources compiled in projects of this configuration. C++ projects may also
+    /// contain C sources.
+    pub fn languages(&self) -> &'static [Language] {
+        match self {
+            Self::C { .. } => &[Language::C],
+            Self::CPP { .. } => &[Language::C, Language::Cpp],
+        }
+    }
+
+    /// Build options of the project, common to all languages
+    pub fn options(&self) -> &BuildOptionsFile {
+        match self {
+            Self::C { opts } | Self::CPP { opts } => opts,
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path).map_err(|err| anyhow::anyhow!("{}: {}", path.display(), err))?;
+        serde_yaml::from_reader(file)
+            .map_err(|err| anyhow::anyhow!("{}: {}", path.display(), err))
+    }
+}
@@ -0,0 +1,239 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    io,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use walkdir::WalkDir;
+
+/// Report written in the build folder by `xay coverage`, in the lcov tracefile format
+pub const LCOV_REPORT: &str = "coverage.info";
+
+/// Execution counts of the lines of a source file, lines which are not executable being left out
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct FileCoverage {
+    pub path: PathBuf,
+    pub lines: BTreeMap<u32, u64>,
+}
+
+impl FileCoverage {
+    /// Number of lines executed at least once
+    pub fn lines_hit(&self) -> usize {
+        self.lines.values().filter(|&&c| c > 0).count()
+    }
+
+    /// Ranges of the lines never executed, such as `12-14,20`
+    pub fn missing(&self) -> String {
+        let mut ranges: Vec<(u32, u32)> = vec![];
+        let executable = self.lines.keys().copied().collect::<Vec<_>>();
+        for (i, (&line, &count)) in self.lines.iter().enumerate() {
+            if count > 0 {
+                continue;
+            }
+            match ranges.last_mut() {
+                // Extends the range over consecutive executable lines
+                Some((_, end)) if i > 0 && executable[i - 1] == *end => *end = line,
+                _ => ranges.push((line, line)),
+            }
+        }
+        ranges
+            .iter()
+            .map(|&(start, end)| match start == end {
+                true => start.to_string(),
+                false => format!("{}-{}", start, end),
+            })
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+/// Command running gcov, from the `GCOV` environment variable such as `llvm-cov gcov` for objects
+/// compiled by clang, `gcov` by default
+pub fn gcov_command() -> anyhow::Result<Vec<String>> {
+    match std::env::var("GCOV") {
+        Ok(value) if !value.trim().is_empty() => shlex::split(&value)
+            .filter(|c| !c.is_empty())
+            .ok_or_else(|| anyhow::anyhow!("GCOV: invalid command {}", value)),
+        _ => Ok(vec!["gcov".to_owned()]),
+    }
+}
+
+/// Removes the execution counts left by previous runs in the build folder, returning the number
+/// of files removed
+pub fn clear_counters(dir: &Path) -> io::Result<usize> {
+    let mut removed = 0;
+    for path in counter_files(dir) {
+        std::fs::remove_file(&path)?;
+        removed += 1;
+    }
+    Ok(removed)
+}
+
+/// Collects the execution counts of the build folder with gcov, keeping the source files for
+/// which `keep` returns true. Files compiled into several objects get their counts summed.
+pub fn collect<F: Fn(&Path) -> bool>(
+    dir: &Path,
+    gcov: &[String],
+    keep: F,
+) -> io::Result<Vec<FileCoverage>> {
+    let mut files: BTreeMap<PathBuf, BTreeMap<u32, u64>> = BTreeMap::new();
+    for data in counter_files(dir) {
+        // Sources are named relative to the build folder, where the compiler ran
+        let output = Command::new(&gcov[0])
+            .args(&gcov[1..])
+            .arg("--stdout")
+            .arg(&data)
+            .current_dir(dir)
+            .output()
+            .map_err(|err| io::Error::new(err.kind(), format!("{}: {}", gcov[0], err)))?;
+        if !output.status.success() {
+            return Err(io::Error::other(format!(
+                "{}: {}",
+                data.display(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        for (source, lines) in parse_gcov(&String::from_utf8_lossy(&output.stdout)) {
+            let path = dir.join(&source);
+            let path = path.canonicalize().unwrap_or(path);
+            if !keep(&path) {
+                continue;
+            }
+            let counts = files.entry(path).or_default();
+            for (line, count) in lines {
+                *counts.entry(line).or_default() += count;
+            }
+        }
+    }
+    Ok(files
+        .into_iter()
+        .map(|(path, lines)| FileCoverage { path, lines })
+        .collect())
+}
+
+/// Parses the text output of gcov, listing the execution counts of each source file
+pub fn parse_gcov(output: &str) -> Vec<(String, BTreeMap<u32, u64>)> {
+    let mut files: Vec<(String, BTreeMap<u32, u64>)> = vec![];
+    for line in output.lines() {
+        let mut parts = line.splitn(3, ':');
+        let (count, number, text) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(count), Some(number), Some(text)) => (count.trim(), number.trim(), text),
+            _ => continue,
+        };
+        if number == "0" {
+            if let Some(source) = text.strip_prefix("Source:") {
+                files.push((source.to_owned(), BTreeMap::new()));
+            }
+            continue;
+        }
+        let (number, lines) = match (number.parse::<u32>(), files.last_mut()) {
+            (Ok(number), Some((_, lines))) => (number, lines),
+            _ => continue,
+        };
+        // `-` marks lines without code, `#####` and `=====` lines never executed, and `*` lines
+        // where some blocks were not executed
+        let count = match count {
+            "-" => continue,
+            "#####" | "=====" => 0,
+            count => match count.trim_end_matches('*').parse() {
+                Ok(count) => count,
+                Err(_) => continue,
+            },
+        };
+        lines.insert(number, count);
+    }
+    files
+}
+
+/// Writes the counts as an lcov tracefile, as read by `genhtml` and most coverage services
+pub fn write_lcov(files: &[FileCoverage], path: &Path) -> io::Result<()> {
+    let mut content = String::new();
+    for file in files {
+        writeln!(content, "SF:{}", file.path.display()).unwrap();
+        for (line, count) in &file.lines {
+            writeln!(content, "DA:{},{}", line, count).unwrap();
+        }
+        writeln!(content, "LF:{}", file.lines.len()).unwrap();
+        writeln!(content, "LH:{}", file.lines_hit()).unwrap();
+        content.push_str("end_of_record\n");
+    }
+    std::fs::write(path, content)
+}
+
+/// Prints the coverage of each file and the total, paths being shown relative to `root`
+pub fn print_summary(files: &[FileCoverage], root: &Path) {
+    let rule = "-".repeat(78);
+    println!("{}", rule);
+    println!("{:<44} {:>7} {:>7} {:>6}   Missing", "File", "Lines", "Exec", "Cover");
+    println!("{}", rule);
+    let (mut total, mut hit) = (0, 0);
+    for file in files {
+        let path = file.path.strip_prefix(root).unwrap_or(&file.path);
+        println!(
+            "{:<44} {:>7} {:>7} {:>6}   {}",
+            path.display(),
+            file.lines.len(),
+            file.lines_hit(),
+            percent(file.lines_hit(), file.lines.len()),
+            file.missing()
+        );
+        total += file.lines.len();
+        hit += file.lines_hit();
+    }
+    println!("{}", rule);
+    println!("{:<44} {:>7} {:>7} {:>6}", "TOTAL", total, hit, percent(hit, total));
+    println!("{}", rule);
+}
+
+fn percent(hit: usize, total: usize) -> String {
+    match total {
+        0 => "--".to_owned(),
+        _ => format!("{}%", hit * 100 / total),
+    }
+}
+
+/// Execution count files of the build folder, written next to the objects
+fn counter_files(dir: &Path) -> impl Iterator<Item = PathBuf> {
+    WalkDir::new(dir)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .map(|e| e.into_path())
+        .filter(|p| p.extension().is_some_and(|ext| ext == "gcda"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_gcov, FileCoverage};
+
+    #[test]
+    fn gcov_output_is_parsed() {
+        let files = parse_gcov(
+            "        -:    0:Source:../../src/main.c\n\
+             \x20       -:    0:Runs:1\n\
+             \x20       -:    1:#include \"a.h\"\n\
+             \x20       1:    2:int main(void) {\n\
+             \x20      1*:    3:  if (f()) return 1;\n\
+             \x20   #####:    4:  g();\n\
+             \x20   #####:    5:  g();\n\
+             \x20       1:    6:  return 0;\n\
+             \x20       -:    0:Source:../../src/a.h\n\
+             \x20      12:    1:static int f(void) { return 0; }\n",
+        );
+        assert_eq!(2, files.len());
+        assert_eq!("../../src/main.c", files[0].0);
+        let main = FileCoverage {
+            path: files[0].0.clone().into(),
+            lines: files[0].1.clone(),
+        };
+        assert_eq!(3, main.lines_hit());
+        assert_eq!("4-5", main.missing());
+        assert_eq!(
+            vec![(2, 1), (3, 1), (4, 0), (5, 0), (6, 1)],
+            main.lines.into_iter().collect::<Vec<_>>()
+        );
+        assert_eq!(vec![(1, 12)], files[1].1.clone().into_iter().collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,126 @@
+use std::{
+    ffi::OsStr,
+    io,
+    path::{Path, PathBuf},
+};
+
+use ninja::Rule;
+
+/// Phony target installing the project
+pub const INSTALL_TARGET: &str = "install";
+
+/// File of the build folder listing the files installed, as read by `xay uninstall`
+pub const MANIFEST: &str = "install_manifest.txt";
+
+/// Rule copying `$in` to `$out` with the `$mode` permissions
+pub fn install_rule() -> Rule {
+    Rule::new("install".to_owned(), "install -m $mode $in $out".to_owned())
+        .set_description("Installing $out".to_owned())
+}
+
+/// Rule creating `$out` as a symbolic link to `$target`
+pub fn symlink_rule() -> Rule {
+    Rule::new("symlink".to_owned(), "ln -sf $target $out".to_owned())
+        .set_description("Linking $out -> $target".to_owned())
+}
+
+/// Path of a file installed at `path` under the prefix, staged in the `DESTDIR` folder when set
+pub fn install_path<P: AsRef<Path>>(prefix: &Path, path: P) -> PathBuf {
+    staged_path(std::env::var_os("DESTDIR").as_deref(), prefix, path.as_ref())
+}
+
+fn staged_path(destdir: Option<&OsStr>, prefix: &Path, path: &Path) -> PathBuf {
+    let path = prefix.join(path);
+    match destdir.filter(|d| !d.is_empty()) {
+        Some(destdir) => Path::new(destdir).join(path.strip_prefix("/").unwrap_or(&path)),
+        None => path,
+    }
+}
+
+/// Writes the list of installed files in the manifest of the build folder. Files are written with
+/// their absolute path, the ones of the build graph being relative to the build folder.
+pub fn write_manifest<S: AsRef<str>>(dir: &Path, files: &[S]) -> io::Result<()> {
+    let mut content = String::new();
+    for file in files {
+        content.push_str(&dir.join(file.as_ref()).display().to_string());
+        content.push('\n');
+    }
+    std::fs::write(dir.join(MANIFEST), content)
+}
+
+/// Removes the files listed in the manifest of the build folder, then the manifest itself once
+/// every file is gone. Returns the number of files removed, files already missing being skipped.
+pub fn uninstall(dir: &Path) -> io::Result<usize> {
+    let manifest = dir.join(MANIFEST);
+    let content = std::fs::read_to_string(&manifest)
+        .map_err(|err| io::Error::new(err.kind(), format!("{}: {}", manifest.display(), err)))?;
+    let mut removed = 0;
+    let mut errors = vec![];
+    for file in content.lines().filter(|l| !l.is_empty()) {
+        let file = dir.join(file);
+        // Symbolic links are removed even when dangling
+        if std::fs::symlink_metadata(&file).is_err() {
+            continue;
+        }
+        println!("Removing {}", file.display());
+        match std::fs::remove_file(&file) {
+            Ok(()) => removed += 1,
+            Err(err) => errors.push(format!("{}: {}", file.display(), err)),
+        }
+    }
+    if !errors.is_empty() {
+        return Err(io::Error::other(format!(
+            "{} files could not be removed, {} is kept:\n  {}",
+            errors.len(),
+            manifest.display(),
+            errors.join("\n  ")
+        )));
+    }
+    std::fs::remove_file(&manifest)?;
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ffi::OsStr, fs, path::Path};
+
+    use super::{staged_path, uninstall, write_manifest, MANIFEST};
+
+    #[test]
+    fn installed_files_are_staged_in_destdir() {
+        let staged = |destdir: Option<&str>, prefix: &str| {
+            staged_path(destdir.map(OsStr::new), Path::new(prefix), Path::new("lib/libfoo.a"))
+        };
+        assert_eq!(Path::new("/usr/local/lib/libfoo.a"), staged(None, "/usr/local"));
+        assert_eq!(Path::new("/usr/local/lib/libfoo.a"), staged(Some(""), "/usr/local"));
+        assert_eq!(
+            Path::new("/tmp/stage/usr/local/lib/libfoo.a"),
+            staged(Some("/tmp/stage"), "/usr/local")
+        );
+        // A relative `DESTDIR` is resolved from the build folder, like the paths of the build file
+        assert_eq!(Path::new("stage/opt/foo/lib/libfoo.a"), staged(Some("stage"), "/opt/foo"));
+    }
+
+    #[test]
+    fn uninstall_keeps_manifest_until_every_file_is_removed() {
+        let dir = std::env::temp_dir().join(format!("xay-uninstall-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("prefix/lib")).unwrap();
+        fs::write(dir.join("prefix/lib/libfoo.a"), "").unwrap();
+        fs::create_dir_all(dir.join("prefix/include/foo/nested")).unwrap();
+        let files = ["prefix/lib/libfoo.a", "prefix/lib/missing.a", "prefix/include/foo"];
+        write_manifest(&dir, &files).unwrap();
+        let manifest = fs::read_to_string(dir.join(MANIFEST)).unwrap();
+        assert!(manifest.lines().all(|l| Path::new(l).is_absolute()));
+
+        // Folders cannot be removed as files
+        assert!(uninstall(&dir).is_err());
+        assert!(!dir.join("prefix/lib/libfoo.a").exists());
+        assert!(dir.join(MANIFEST).exists());
+
+        fs::remove_dir_all(dir.join("prefix/include/foo")).unwrap();
+        assert_eq!(0, uninstall(&dir).unwrap());
+        assert!(!dir.join(MANIFEST).exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,8 +1,13 @@
 use std::ops::{Deref, DerefMut};
 use std::path::{PathBuf};
 
+use crate::config::Configuration;
+
 pub mod config;
+pub mod coverage;
+pub mod install;
 pub mod clike;
+pub mod testing;
 pub mod utils;
 
 #[derive(Clone, Debug, Eq, PartialEq)]
@@ -10,7 +15,13 @@ pub struct Context<T> {
     pub name: String,
     pub cwd: PathBuf,
     pub src_dir: PathBuf,
+    pub lib_dir: PathBuf,
+    pub tests_dir: PathBuf,
     pub dest_dir: PathBuf,
+    /// Folder the project is installed into
+    pub prefix: PathBuf,
+    /// Whether to print the toolchain and the details of the generation of the build files
+    pub verbose: bool,
     pub inner: T,
 }
 
@@ -32,12 +43,19 @@ impl Context<()> {
     pub fn new(cwd: PathBuf) -> Self {
         let name = cwd.file_name().unwrap().to_string_lossy().into_owned();
         let src_dir = cwd.join("src");
+        let lib_dir = cwd.join("lib");
+        let tests_dir = cwd.join("tests");
         let dest_dir = cwd.join("build");
+        let prefix = PathBuf::from("/usr/local");
         Self {
             name,
             cwd,
             src_dir,
+            lib_dir,
+            tests_dir,
             dest_dir,
+            prefix,
+            verbose: false,
             inner: ()
         }
     }
@@ -50,6 +68,10 @@ impl<T> Context<T> {
             cwd,
             dest_dir,
             src_dir,
+            lib_dir,
+            tests_dir,
+            prefix,
+            verbose,
             inner,
         } = self;
         Context {
@@ -57,7 +79,34 @@ impl<T> Context<T> {
             cwd,
             dest_dir,
             src_dir,
+            lib_dir,
+            tests_dir,
+            prefix,
+            verbose,
             inner: f(inner),
         }
     }
+
+    /// Source dependencies of the project, one per child of the `lib` folder. Dependencies share
+    /// the destination folder and the prefix of the project depending on them.
+    pub fn dependencies(&self) -> anyhow::Result<Vec<Context<Configuration>>> {
+        if !self.lib_dir.is_dir() {
+            return Ok(vec![]);
+        }
+        let mut dirs = std::fs::read_dir(&self.lib_dir)?
+            .map(|e| e.map(|e| e.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+        dirs.retain(|p| p.is_dir());
+        dirs.sort();
+        dirs.into_iter()
+            .map(|dir| {
+                let mut ctx = Context::new(dir);
+                ctx.dest_dir = self.dest_dir.clone();
+                ctx.prefix = self.prefix.clone();
+                ctx.verbose = self.verbose;
+                let config = Configuration::load(ctx.cwd.join("xay.yml"))?;
+                Ok(ctx.map_inner(|_| config))
+            })
+            .collect()
+    }
 }
@@ -0,0 +1,98 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+    process::{Command, ExitStatus},
+    sync::{mpsc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Outcome of a test executable
+#[derive(Debug)]
+pub struct TestResult {
+    pub name: String,
+    pub status: ExitStatus,
+    pub duration: Duration,
+    /// Standard output followed by the standard error of the test
+    pub output: String,
+}
+
+impl TestResult {
+    pub fn success(&self) -> bool {
+        self.status.success()
+    }
+}
+
+/// Runs a test executable from `cwd`, capturing its output
+pub fn run_test(name: &str, path: &Path, cwd: &Path) -> io::Result<TestResult> {
+    let start = Instant::now();
+    let output = Command::new(path)
+        .current_dir(cwd)
+        .output()
+        .map_err(|err| io::Error::new(err.kind(), format!("{}: {}", path.display(), err)))?;
+    let mut text = String::from_utf8_lossy(&output.stdout).into_owned();
+    text.push_str(&String::from_utf8_lossy(&output.stderr));
+    Ok(TestResult {
+        name: name.to_owned(),
+        status: output.status,
+        duration: start.elapsed(),
+        output: text,
+    })
+}
+
+/// Runs the named test executables from `cwd`, `jobs` at a time, printing the result of each as
+/// it finishes and the output of the failed ones at the end. Returns whether all tests passed.
+pub fn run_tests(tests: &[(String, PathBuf)], cwd: &Path, jobs: usize) -> io::Result<bool> {
+    println!("running {} tests", tests.len());
+    let start = Instant::now();
+    let queue = Mutex::new(tests.iter());
+    let (tx, rx) = mpsc::channel();
+    let results = thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, tests.len().max(1)) {
+            let tx = tx.clone();
+            let queue = &queue;
+            scope.spawn(move || loop {
+                let next = queue.lock().unwrap().next();
+                let (name, path) = match next {
+                    Some(test) => test,
+                    None => break,
+                };
+                // The receiver is gone when a test could not be started
+                if tx.send(run_test(name, path, cwd)).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(tx);
+        let mut results = vec![];
+        for result in rx {
+            let result = result?;
+            let verdict = if result.success() { "ok" } else { "FAILED" };
+            println!(
+                "test {} ... {} ({:.2}s)",
+                result.name,
+                verdict,
+                result.duration.as_secs_f64()
+            );
+            results.push(result);
+        }
+        Ok::<_, io::Error>(results)
+    })?;
+
+    let failed = results.iter().filter(|r| !r.success()).collect::<Vec<_>>();
+    if !failed.is_empty() {
+        println!("\nfailures:");
+        for result in &failed {
+            println!("\n---- {} ({}) ----", result.name, result.status);
+            print!("{}", result.output);
+        }
+    }
+    println!(
+        "\ntest result: {}. {} passed; {} failed; finished in {:.2}s",
+        if failed.is_empty() { "ok" } else { "FAILED" },
+        results.len() - failed.len(),
+        failed.len(),
+        start.elapsed().as_secs_f64()
+    );
+    Ok(failed.is_empty())
+}
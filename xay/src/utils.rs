@@ -1,4 +1,7 @@
-use std::path::{PathBuf, Path};
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
 
 #[cfg(not(target_os = "windows"))]
 const PATHSEP: char = ':';
@@ -22,4 +25,12 @@ pub fn shell_join<I: Iterator<Item = String>>(values: I) -> String {
         .map(|s| shlex::quote(&s).into())
         .collect::<Vec<String>>()
         .join(" ")
-}
\ No newline at end of file
+}
+/// Writes `contents` to `path`, leaving the file untouched when it already holds them so that
+/// its modification time does not make the builds depending on it run again
+pub fn write_if_changed(path: &Path, contents: &str) -> io::Result<()> {
+    if std::fs::read_to_string(path).is_ok_and(|c| c == contents) {
+        return Ok(());
+    }
+    std::fs::write(path, contents)
+}